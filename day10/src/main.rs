@@ -1,8 +1,9 @@
 /* Cathode-Ray Tube
 
-HandHeld CPU single register (X) starts up with a value of 1. Supports two isns
+HandHeld CPU register X starts up with a value of 1, Y with 0. The puzzle uses two isns
 * addx [signed] ; cost 2 cycles
 * noop ; cost 1 cycle
+the rest of the instruction set is declared in ISA
 
 noop        [during cycle 1 X=1]
 addx 3      [during cycle 2 X=1, after cycle 3, X=4]
@@ -10,6 +11,8 @@ addx -5     [during cycle 4 X=4, after cycle 5 X=-1]
 
 Signal strenth: cycle number * X register value
 */
+use std::collections::HashMap;
+use std::convert::TryFrom;
 use std::env;
 use std::fmt;
 use std::fs::File;
@@ -20,6 +23,20 @@ use std::str::FromStr;
 #[derive(Debug, PartialEq, Eq)]
 enum Day10Error {
     OpParseError,
+    Io,
+    InvalidOp { line: usize },
+    UnknownLabel { line: usize, label: String },
+    DuplicateLabel { line: usize, label: String },
+    /// OCR needs frames exactly one glyph tall
+    FrameSize { width: usize, height: usize },
+    /// glyph positions, counted from the left, that are not in the font
+    UnrecognisedGlyphs(Vec<usize>),
+    /// the pc left the program
+    Halted { pc: isize },
+    /// arithmetic on the machine state overflowed during the cycle
+    Overflow { cycle: isize },
+    /// the program was still running after this many cycles
+    CycleLimit { limit: usize },
 }
 impl fmt::Display for Day10Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -27,166 +44,1169 @@ impl fmt::Display for Day10Error {
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+/// HandHeld registers, X drives the signal and the CRT sprite
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Register {
+    X,
+    Y,
+}
+impl Register {
+    const ALL:[Register; 2] = [Register::X, Register::Y];
+
+    fn name(self) -> &'static str {
+        match self {
+            Register::X => "x",
+            Register::Y => "y",
+        }
+    }
+}
+impl FromStr for Register {
+    type Err = Day10Error;
+    fn from_str(v:&str) -> Result<Self, Day10Error> {
+        Register::ALL.iter().copied().find(|r| r.name() == v).ok_or(Day10Error::OpParseError)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum OperandKind {
+    Register,
+    /// signed value
+    Immediate,
+    /// jump offset relative to the jump, written +N/-N or as a label in assembly
+    Target,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operand {
+    Register(Register),
+    Immediate(isize),
+    Target(isize),
+}
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Operand::Register(r) => write!(f, "{}", r.name()),
+            Operand::Immediate(v) => write!(f, "{}", v),
+            Operand::Target(offset) => write!(f, "{:+}", offset),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Opcode {
+    Noop,
+    Addx,
+    Addy,
+    Mulx,
+    Jmp,
+    Jz,
+    Jnz,
+}
+
+/// Encoding and timing of one instruction
+#[derive(Debug)]
+struct OpSpec {
+    opcode: Opcode,
+    mnemonic: &'static str,
+    operands: &'static [OperandKind],
+    /// build the op from operands already checked against `operands`
+    decode: fn(&[Operand]) -> Option<Op>,
+    cycles: usize,
+}
+
+/// The instruction set, adding an instruction starts here: assembly, decoding, arity and
+/// timing all come from this table, `Op::operands` lists the operands back in the same order
+static ISA:[OpSpec; 7] = {
+    use Operand::*;
+    [
+        OpSpec{opcode: Opcode::Noop, mnemonic: "noop", operands: &[], cycles: 1,
+            decode: |operands| match operands { [] => Some(Op::Noop), _ => None }},
+        OpSpec{opcode: Opcode::Addx, mnemonic: "addx", operands: &[OperandKind::Immediate], cycles: 2,
+            decode: |operands| match operands { [Immediate(v)] => Some(Op::Addx(*v)), _ => None }},
+        OpSpec{opcode: Opcode::Addy, mnemonic: "addy", operands: &[OperandKind::Immediate], cycles: 2,
+            decode: |operands| match operands { [Immediate(v)] => Some(Op::Addy(*v)), _ => None }},
+        OpSpec{opcode: Opcode::Mulx, mnemonic: "mulx", operands: &[OperandKind::Immediate], cycles: 3,
+            decode: |operands| match operands { [Immediate(v)] => Some(Op::Mulx(*v)), _ => None }},
+        OpSpec{opcode: Opcode::Jmp, mnemonic: "jmp", operands: &[OperandKind::Target], cycles: 1,
+            decode: |operands| match operands { [Target(t)] => Some(Op::Jmp(*t)), _ => None }},
+        OpSpec{opcode: Opcode::Jz, mnemonic: "jz", operands: &[OperandKind::Register, OperandKind::Target], cycles: 2,
+            decode: |operands| match operands { [Register(r), Target(t)] => Some(Op::Jz(*r, *t)), _ => None }},
+        OpSpec{opcode: Opcode::Jnz, mnemonic: "jnz", operands: &[OperandKind::Register, OperandKind::Target], cycles: 2,
+            decode: |operands| match operands { [Register(r), Target(t)] => Some(Op::Jnz(*r, *t)), _ => None }},
+    ]
+};
+
+impl Opcode {
+    fn spec(self) -> &'static OpSpec {
+        ISA.iter().find(|spec| spec.opcode == self).expect("opcode missing from ISA")
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum Op {
-    NOOP,
-    ADDX(isize),
+    Noop,
+    Addx(isize),
+    Addy(isize),
+    Mulx(isize),
+    /// relative jump
+    Jmp(isize),
+    /// relative jump when the register is zero
+    Jz(Register, isize),
+    /// relative jump when the register is not zero
+    Jnz(Register, isize),
+}
+impl Op {
+    fn opcode(&self) -> Opcode {
+        match self {
+            Op::Noop => Opcode::Noop,
+            Op::Addx(_) => Opcode::Addx,
+            Op::Addy(_) => Opcode::Addy,
+            Op::Mulx(_) => Opcode::Mulx,
+            Op::Jmp(_) => Opcode::Jmp,
+            Op::Jz(..) => Opcode::Jz,
+            Op::Jnz(..) => Opcode::Jnz,
+        }
+    }
+
+    fn spec(&self) -> &'static OpSpec {
+        self.opcode().spec()
+    }
+
+    fn operands(&self) -> Vec<Operand> {
+        match *self {
+            Op::Noop => vec![],
+            Op::Addx(v) | Op::Addy(v) | Op::Mulx(v) => vec![Operand::Immediate(v)],
+            Op::Jmp(t) => vec![Operand::Target(t)],
+            Op::Jz(r, t) | Op::Jnz(r, t) => vec![Operand::Register(r), Operand::Target(t)],
+        }
+    }
+
+    /// Parse an instruction, label resolves a jump target that is not a +N/-N offset
+    fn parse_with<F: FnMut(&str) -> Option<isize>>(v:&str, mut label:F) -> Result<Op, Day10Error> {
+        let mut words = v.split_whitespace();
+        let mnemonic = words.next().ok_or(Day10Error::OpParseError)?;
+        let spec = ISA.iter().find(|spec| spec.mnemonic == mnemonic).ok_or(Day10Error::OpParseError)?;
+        let words:Vec<&str> = words.collect();
+        if words.len() != spec.operands.len() {
+            return Err(Day10Error::OpParseError);
+        }
+        let operands = spec.operands.iter().zip(words).map(|(kind, word)| match kind {
+            OperandKind::Register => word.parse().map(Operand::Register),
+            OperandKind::Immediate => word.parse().map(Operand::Immediate).map_err(|_| Day10Error::OpParseError),
+            OperandKind::Target if word.starts_with(['+', '-']) => {
+                word.parse().map(Operand::Target).map_err(|_| Day10Error::OpParseError)
+            },
+            OperandKind::Target => label(word).map(Operand::Target).ok_or(Day10Error::OpParseError),
+        }).collect::<Result<Vec<Operand>, Day10Error>>()?;
+        (spec.decode)(&operands).ok_or(Day10Error::OpParseError)
+    }
 }
 impl fmt::Display for Op {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{:?}", self)
+        write!(f, "{}", self.spec().mnemonic)?;
+        for operand in self.operands() {
+            write!(f, " {}", operand)?;
+        }
+        Ok(())
     }
 }
 impl FromStr for Op {
     type Err = Day10Error;
     fn from_str(v:&str) -> Result<Self, Day10Error> {
-        match v.split(" ").collect::<Vec<&str>>().as_slice() {
-            // double word
-            [o, v] => {
-                match *o {
-                    "addx" => {
-                        match v.parse() {
-                            Ok(opt) => Ok(Op::ADDX(opt)),
-                            _ => Err(Day10Error::OpParseError),
-                        }
-                    },
-                    _ => Err(Day10Error::OpParseError)
-                }
+        Op::parse_with(v, |_| None)
+    }
+}
+
+static COMMENT_PREFIX:char = ';';
+static LABEL_SUFFIX:char = ':';
+
+/// Assemble a program: one instruction per line, `name:` labels its line or the next
+/// instruction, `;` starts a comment and jumps may name a label instead of an offset.
+fn assemble<R: Read>(reader:BufReader<R>) -> Result<Vec<Op>, Day10Error> {
+    let mut lines:Vec<(usize, String)> = Vec::new();
+    let mut labels:HashMap<String, usize> = HashMap::new();
+    for (idx, line) in reader.lines().enumerate() {
+        let line = line.map_err(|_| Day10Error::Io)?;
+        let mut code = line.split(COMMENT_PREFIX).next().unwrap_or("").trim();
+        if let Some((label, rest)) = code.split_once(LABEL_SUFFIX) {
+            let label = label.trim();
+            if label.is_empty() || label.contains(char::is_whitespace) {
+                return Err(Day10Error::InvalidOp{line: idx + 1});
+            }
+            if labels.insert(label.to_string(), lines.len()).is_some() {
+                return Err(Day10Error::DuplicateLabel{line: idx + 1, label: label.to_string()});
+            }
+            code = rest.trim();
+        }
+        if !code.is_empty() {
+            lines.push((idx + 1, code.to_string()));
+        }
+    }
+
+    lines.iter().enumerate().map(|(address, (line, code))| {
+        let mut missing = None;
+        let op = Op::parse_with(code, |label| match labels.get(label) {
+            Some(&target) => Some(target as isize - address as isize),
+            None => {
+                missing = Some(label.to_string());
+                None
             },
-            [o] => {
-                match *o {
-                    "noop" => Ok(Op::NOOP),
-                    _ => Err(Day10Error::OpParseError),
-                }
+        });
+        op.map_err(|_| match missing.take() {
+            Some(label) => Day10Error::UnknownLabel{line: *line, label},
+            None => Day10Error::InvalidOp{line: *line},
+        })
+    }).collect()
+}
+
+/// cycles a program may run for before it is assumed to never halt, also what a bare `c`
+/// runs for in the debugger
+const MAX_CYCLES:usize = 1_000_000;
+static CRT_WIDTH:usize = 40;
+static CRT_HEIGHT:usize = 6;
+static CRT_BLANK_PIXEL:char = '.';
+static CRT_ACTIVE_PIXEL:char = '#';
+
+/// A full CRT frame, row major
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Framebuffer {
+    width: usize,
+    height: usize,
+    pixels: Vec<bool>,
+}
+impl Framebuffer {
+    fn rows(&self) -> impl Iterator<Item = &[bool]> {
+        self.pixels.chunks(self.width)
+    }
+}
+
+/// Somewhere to show a CRT frame
+trait CrtDisplay {
+    fn draw(&self, frame:&Framebuffer, out:&mut dyn Write) -> std::io::Result<()>;
+}
+
+/// `#` and `.` like the puzzle
+struct AsciiDisplay;
+impl CrtDisplay for AsciiDisplay {
+    fn draw(&self, frame:&Framebuffer, out:&mut dyn Write) -> std::io::Result<()> {
+        for row in frame.rows() {
+            writeln!(out, "{}", row.iter().map(|&lit| if lit {CRT_ACTIVE_PIXEL} else {CRT_BLANK_PIXEL}).collect::<String>())?;
+        }
+        Ok(())
+    }
+}
+
+/// Terminal blocks using background colours, two columns per pixel so letters keep their shape
+struct AnsiDisplay;
+impl CrtDisplay for AnsiDisplay {
+    fn draw(&self, frame:&Framebuffer, out:&mut dyn Write) -> std::io::Result<()> {
+        for row in frame.rows() {
+            for &lit in row {
+                write!(out, "\x1b[{}m  ", if lit {102} else {40})?;
+            }
+            writeln!(out, "\x1b[0m")?;
+        }
+        Ok(())
+    }
+}
+
+/// Plain (P1) portable bitmap, lit pixels are black
+struct PbmDisplay;
+impl CrtDisplay for PbmDisplay {
+    fn draw(&self, frame:&Framebuffer, out:&mut dyn Write) -> std::io::Result<()> {
+        writeln!(out, "P1\n{} {}", frame.width, frame.height)?;
+        for row in frame.rows() {
+            writeln!(out, "{}", row.iter().map(|&lit| if lit {"1"} else {"0"}).collect::<Vec<&str>>().join(" "))?;
+        }
+        Ok(())
+    }
+}
+
+static GLYPH_WIDTH:usize = 4;
+static GLYPH_HEIGHT:usize = 6;
+/// blank column between letters
+static GLYPH_SPACING:usize = 1;
+
+/// The letters the puzzles draw with, `#` lit
+static FONT:[(char, [&str; 6]); 17] = [
+    ('A', [".##.", "#..#", "#..#", "####", "#..#", "#..#"]),
+    ('B', ["###.", "#..#", "###.", "#..#", "#..#", "###."]),
+    ('C', [".##.", "#..#", "#...", "#...", "#..#", ".##."]),
+    ('E', ["####", "#...", "###.", "#...", "#...", "####"]),
+    ('F', ["####", "#...", "###.", "#...", "#...", "#..."]),
+    ('G', [".##.", "#..#", "#...", "#.##", "#..#", ".###"]),
+    ('H', ["#..#", "#..#", "####", "#..#", "#..#", "#..#"]),
+    ('I', [".###", "..#.", "..#.", "..#.", "..#.", ".###"]),
+    ('J', ["..##", "...#", "...#", "...#", "#..#", ".##."]),
+    ('K', ["#..#", "#.#.", "##..", "#.#.", "#.#.", "#..#"]),
+    ('L', ["#...", "#...", "#...", "#...", "#...", "####"]),
+    ('O', [".##.", "#..#", "#..#", "#..#", "#..#", ".##."]),
+    ('P', ["###.", "#..#", "#..#", "###.", "#...", "#..."]),
+    ('R', ["###.", "#..#", "#..#", "###.", "#.#.", "#..#"]),
+    ('S', [".###", "#...", "#...", ".##.", "...#", "###."]),
+    ('U', ["#..#", "#..#", "#..#", "#..#", "#..#", ".##."]),
+    ('Z', ["####", "...#", "..#.", ".#..", "#...", "####"]),
+];
+
+/// Read the letters drawn on a frame
+fn ocr(frame:&Framebuffer) -> Result<String, Day10Error> {
+    if frame.height != GLYPH_HEIGHT || frame.width < GLYPH_WIDTH {
+        return Err(Day10Error::FrameSize{width: frame.width, height: frame.height});
+    }
+    let glyphs = (frame.width + GLYPH_SPACING) / (GLYPH_WIDTH + GLYPH_SPACING);
+    let mut text = String::with_capacity(glyphs);
+    let mut unrecognised = Vec::new();
+    for glyph in 0..glyphs {
+        let left = glyph * (GLYPH_WIDTH + GLYPH_SPACING);
+        let matches = |rows:&[&str; 6]| rows.iter().zip(frame.rows()).all(|(font_row, row)| {
+            font_row.chars().zip(&row[left..left + GLYPH_WIDTH]).all(|(c, &lit)| (c == CRT_ACTIVE_PIXEL) == lit)
+        });
+        match FONT.iter().find(|(_, rows)| matches(rows)) {
+            Some((letter, _)) => text.push(*letter),
+            None => unrecognised.push(glyph),
+        }
+    }
+    if !unrecognised.is_empty() {
+        return Err(Day10Error::UnrecognisedGlyphs(unrecognised));
+    }
+    Ok(text)
+}
+
+/// What the HandHeld did, recorded when tracing
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TraceEvent {
+    /// op started executing
+    Fetch(Op),
+    /// op finished and took effect
+    Retire(Op),
+    /// signal strength sampled this cycle
+    Signal(isize),
+    /// a debugger watch expression changed
+    Watch { watch: usize, value: isize },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct TraceRecord {
+    cycle: isize,
+    pc: isize,
+    registers: [isize; 2],
+    event: TraceEvent,
+}
+impl TraceRecord {
+    fn to_json(self) -> String {
+        let event = match self.event {
+            TraceEvent::Fetch(op) => format!("\"event\":\"fetch\",\"op\":\"{}\"", op),
+            TraceEvent::Retire(op) => format!("\"event\":\"retire\",\"op\":\"{}\"", op),
+            TraceEvent::Signal(strength) => format!("\"event\":\"signal\",\"strength\":{}", strength),
+            TraceEvent::Watch{watch, value} => format!("\"event\":\"watch\",\"watch\":{},\"value\":{}", watch, value),
+        };
+        let registers:Vec<String> = Register::ALL.iter().map(|&r| format!("\"{}\":{}", r.name(), self.registers[r as usize])).collect();
+        format!("{{\"cycle\":{},\"pc\":{},{},{}}}", self.cycle, self.pc, registers.join(","), event)
+    }
+}
+
+/// A signal strength sample: cycle, X and their product
+type Sample = (isize, isize, isize);
+
+/// Which cycles to sample the signal on
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Schedule {
+    Cycles(Vec<isize>),
+    /// the first cycle and every period cycles after
+    Periodic { first: isize, period: isize },
+}
+impl Schedule {
+    fn includes(&self, cycle:isize) -> bool {
+        match self {
+            Schedule::Cycles(cycles) => cycles.contains(&cycle),
+            Schedule::Periodic{first, period} => cycle >= *first && (cycle - first) % period == 0,
+        }
+    }
+}
+impl FromStr for Schedule {
+    type Err = Day10Error;
+    /// `20+40` for periodic, `20,60,100` for a list
+    fn from_str(v:&str) -> Result<Self, Day10Error> {
+        match v.split_once('+') {
+            Some((first, period)) => match (first.parse(), period.parse()) {
+                (Ok(first), Ok(period)) if period > 0 => Ok(Schedule::Periodic{first, period}),
+                _ => Err(Day10Error::OpParseError),
             },
-            _ => Err(Day10Error::OpParseError),
+            None => v.split(',').map(|c| c.parse().map_err(|_| Day10Error::OpParseError)).collect::<Result<Vec<isize>, Day10Error>>().map(Schedule::Cycles),
         }
     }
 }
 
-static CRT_BUF_LEN:usize = 40;
-static CRT_BLANK_PIXEL:char = '░';
-static CRT_ACTIVE_PIXEL:char = '▓';
+/// Whether a sample sees X during the cycle or after the op retiring in it has taken effect
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SamplePhase {
+    During,
+    After,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Sampling {
+    schedule: Schedule,
+    phase: SamplePhase,
+}
+impl Default for Sampling {
+    /// the puzzle samples during cycle 20 and every 40 after
+    fn default() -> Self {
+        Self{schedule: Schedule::Periodic{first: 20, period: 40}, phase: SamplePhase::During}
+    }
+}
+
+/// Ways to boil the samples down to one number
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Reducer {
+    Sum,
+    Max,
+    Min,
+    Count,
+    Last,
+}
+impl Reducer {
+    /// None when there is nothing to reduce or the sum overflows
+    fn reduce<I: Iterator<Item = Sample>>(self, samples:I) -> Option<isize> {
+        let mut strengths = samples.map(|(_, _, strength)| strength);
+        match self {
+            Reducer::Sum => strengths.try_fold(0isize, |acc, strength| acc.checked_add(strength)),
+            Reducer::Max => strengths.max(),
+            Reducer::Min => strengths.min(),
+            Reducer::Count => Some(strengths.count() as isize),
+            Reducer::Last => strengths.last(),
+        }
+    }
+}
+impl FromStr for Reducer {
+    type Err = Day10Error;
+    fn from_str(v:&str) -> Result<Self, Day10Error> {
+        match v {
+            "sum" => Ok(Reducer::Sum),
+            "max" => Ok(Reducer::Max),
+            "min" => Ok(Reducer::Min),
+            "count" => Ok(Reducer::Count),
+            "last" => Ok(Reducer::Last),
+            _ => Err(Day10Error::OpParseError),
+        }
+    }
+}
 
 #[derive(Debug)]
 struct HandHeld {
     cycle_counter: isize,
-    register_x: isize,
-    signal_strenth_nvram: Vec<isize>,
+    registers: [isize; 2],
+    /// index of the next op when running a program
+    pc: isize,
+    /// op being executed and the cycles it still needs
+    in_flight: Option<(Op, usize)>,
+    sampling: Sampling,
+    signal_strenth_nvram: Vec<Sample>,
+    crt_width: usize,
+    crt_height: usize,
+    /// pixels drawn so far, wrapping to the top once the screen is full
     crt_buffer: Vec<char>,
+    /// everything the machine did, None when not tracing
+    trace: Option<Vec<TraceRecord>>,
 }
 impl HandHeld {
-    fn cycle_accounting(&mut self) {
+    fn cycle_accounting(&mut self) -> Result<(), Day10Error> {
         self.cycle_counter += 1;
-        self.signal_timer();
+        self.signal_timer(SamplePhase::During)?;
         self.crt_timer();
+        Ok(())
+    }
+
+    fn register(&self, r:Register) -> isize {
+        self.registers[r as usize]
     }
 
-    fn execute_op(&mut self, op:Op) {
-        println!("{} performing operation {}", self, op);
+    fn record(&mut self, event:TraceEvent) {
+        let record = TraceRecord{cycle: self.cycle_counter, pc: self.pc, registers: self.registers, event};
+        if let Some(trace) = &mut self.trace {
+            trace.push(record);
+        }
+    }
+
+    fn fetch(&mut self, op:Op) {
+        self.in_flight = Some((op, op.spec().cycles));
+        self.record(TraceEvent::Fetch(op));
+    }
+
+    /// Run one cycle of the op in flight, returning it once it has retired
+    fn tick(&mut self) -> Result<Option<Op>, Day10Error> {
+        let (op, remaining) = match self.in_flight.take() {
+            Some(in_flight) => in_flight,
+            None => return Ok(None),
+        };
+        self.cycle_accounting()?;
+        let retired = if remaining > 1 {
+            self.in_flight = Some((op, remaining - 1));
+            None
+        } else {
+            self.retire(op)?;
+            Some(op)
+        };
+        self.signal_timer(SamplePhase::After)?;
+        Ok(retired)
+    }
+
+    /// Apply the op, leaving the machine untouched if a register or the pc would overflow
+    fn retire(&mut self, op:Op) -> Result<(), Day10Error> {
+        let cycle = self.cycle_counter;
+        let overflow = || Day10Error::Overflow{cycle};
+        let (x, y) = (Register::X as usize, Register::Y as usize);
+        let mut registers = self.registers;
+        let pc = match op {
+            Op::Jmp(t) => self.pc.checked_add(t),
+            Op::Jz(r, t) if self.register(r) == 0 => self.pc.checked_add(t),
+            Op::Jnz(r, t) if self.register(r) != 0 => self.pc.checked_add(t),
+            _ => self.pc.checked_add(1),
+        }.ok_or_else(overflow)?;
         match op {
-            Op::ADDX(v) => { // 2 cycles
-                self.cycle_accounting();
-                self.cycle_accounting();
-                self.register_x += v;
-            },
-            Op::NOOP => { // 1 cycle
-                self.cycle_accounting();
-            },
+            Op::Addx(v) => registers[x] = registers[x].checked_add(v).ok_or_else(overflow)?,
+            Op::Addy(v) => registers[y] = registers[y].checked_add(v).ok_or_else(overflow)?,
+            Op::Mulx(v) => registers[x] = registers[x].checked_mul(v).ok_or_else(overflow)?,
+            Op::Noop | Op::Jmp(_) | Op::Jz(..) | Op::Jnz(..) => {},
+        }
+        self.pc = pc;
+        self.registers = registers;
+        self.record(TraceEvent::Retire(op));
+        Ok(())
+    }
+
+    fn execute_op(&mut self, op:Op) -> Result<(), Day10Error> {
+        self.fetch(op);
+        while self.tick()?.is_none() {}
+        Ok(())
+    }
+
+    fn next_op(&self, program:&[Op]) -> Option<Op> {
+        usize::try_from(self.pc).ok().and_then(|pc| program.get(pc)).copied()
+    }
+
+    /// Run one cycle of the program, fetching the op at the pc when none is in flight.
+    /// Returns the op retired by the cycle, or `Halted` once the pc has left the program.
+    fn step_cycle(&mut self, program:&[Op]) -> Result<Option<Op>, Day10Error> {
+        if self.in_flight.is_none() {
+            let op = self.next_op(program).ok_or(Day10Error::Halted{pc: self.pc})?;
+            self.fetch(op);
         }
+        self.tick()
     }
 
-    fn signal_timer(&mut self) {
-        if self.cycle_counter == 20 || (self.cycle_counter - 20) % 40 == 0 {
-            let signal_strength = self.cycle_counter * self.register_x;
-            println!("{} signal strength for cycle {} is {}", self, self.cycle_counter, signal_strength);
-            self.signal_strenth_nvram.push(signal_strength);
+    /// Execute until the pc leaves the program, giving up before an op would take the run
+    /// past max_cycles
+    fn run(&mut self, program:&[Op], max_cycles:usize) -> Result<(), Day10Error> {
+        let start = self.cycle_counter;
+        while let Some(op) = self.next_op(program) {
+            if (self.cycle_counter - start) as usize + op.spec().cycles > max_cycles {
+                return Err(Day10Error::CycleLimit{limit: max_cycles});
+            }
+            self.execute_op(op)?;
         }
+        Ok(())
+    }
+
+    fn signal_timer(&mut self, phase:SamplePhase) -> Result<(), Day10Error> {
+        if self.sampling.phase == phase && self.sampling.schedule.includes(self.cycle_counter) {
+            let x = self.register(Register::X);
+            let signal_strength = self.cycle_counter.checked_mul(x).ok_or(Day10Error::Overflow{cycle: self.cycle_counter})?;
+            self.record(TraceEvent::Signal(signal_strength));
+            self.signal_strenth_nvram.push((self.cycle_counter, x, signal_strength));
+        }
+        Ok(())
+    }
+
+    /// Samples taken so far
+    fn samples(&self) -> impl Iterator<Item = Sample> + '_ {
+        self.signal_strenth_nvram.iter().copied()
     }
     fn crt_timer(&mut self) {
-        let pixel = self.crt_buffer.len() as isize;
-        let x = self.register_x;
-        self.crt_buffer.push(if pixel == x || pixel == (x-1) || pixel == (x+1) {CRT_ACTIVE_PIXEL} else {CRT_BLANK_PIXEL});
-        if self.cycle_counter % (CRT_BUF_LEN as isize) == 0 {
-            println!("CRT: {}", self.crt_buffer.drain(..).collect::<String>());
+        let pixel = (self.cycle_counter - 1) as usize % (self.crt_width * self.crt_height);
+        let column = (pixel % self.crt_width) as isize;
+        let x = self.register(Register::X);
+        let c = if column.abs_diff(x) <= 1 {CRT_ACTIVE_PIXEL} else {CRT_BLANK_PIXEL};
+        match self.crt_buffer.get_mut(pixel) {
+            Some(old) => *old = c,
+            None => self.crt_buffer.push(c),
         }
     }
 
+    fn framebuffer(&self) -> Framebuffer {
+        let mut pixels:Vec<bool> = self.crt_buffer.iter().map(|&c| c == CRT_ACTIVE_PIXEL).collect();
+        pixels.resize(self.crt_width * self.crt_height, false);
+        Framebuffer{width: self.crt_width, height: self.crt_height, pixels}
+    }
+
+    fn with_crt(crt_width:usize, crt_height:usize) -> Self {
+        Self{crt_width, crt_height, crt_buffer: Vec::with_capacity(crt_width * crt_height), ..Self::default()}
+    }
 }
 impl Default for HandHeld {
     fn default() -> Self {
-        Self{cycle_counter: 0, register_x: 1, signal_strenth_nvram: Vec::new(), crt_buffer: Vec::with_capacity(CRT_BUF_LEN)}
+        Self{cycle_counter: 0, registers: [1, 0], pc: 0, in_flight: None, sampling: Sampling::default(), signal_strenth_nvram: Vec::new(), crt_width: CRT_WIDTH, crt_height: CRT_HEIGHT, crt_buffer: Vec::new(), trace: None}
     }
 }
 impl fmt::Display for HandHeld {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "[HandHeld({}: {}]", self.cycle_counter, self.register_x)
+        write!(f, "[HandHeld({}: {}]", self.cycle_counter, self.register(Register::X))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Term {
+    Register(Register),
+    Cycle,
+    Pc,
+    Value(isize),
+}
+impl FromStr for Term {
+    type Err = Day10Error;
+    fn from_str(v:&str) -> Result<Self, Day10Error> {
+        match v {
+            "cycle" => Ok(Term::Cycle),
+            "pc" => Ok(Term::Pc),
+            _ => v.parse().map(Term::Register).or_else(|_| v.parse().map(Term::Value).map_err(|_| Day10Error::OpParseError)),
+        }
+    }
+}
+impl fmt::Display for Term {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Term::Register(r) => write!(f, "{}", r.name()),
+            Term::Cycle => write!(f, "cycle"),
+            Term::Pc => write!(f, "pc"),
+            Term::Value(v) => write!(f, "{}", v),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BinOp {
+    Add,
+    Sub,
+    Mul,
+    Eq,
+    Ne,
+    Lt,
+    Gt,
+}
+impl BinOp {
+    const ALL:[(BinOp, &'static str); 7] = [
+        (BinOp::Add, "+"), (BinOp::Sub, "-"), (BinOp::Mul, "*"),
+        (BinOp::Eq, "=="), (BinOp::Ne, "!="), (BinOp::Lt, "<"), (BinOp::Gt, ">"),
+    ];
+
+    fn symbol(self) -> &'static str {
+        BinOp::ALL.iter().find(|(op, _)| *op == self).map(|(_, s)| *s).unwrap_or("?")
+    }
+
+    /// None on overflow
+    fn apply(self, a:isize, b:isize) -> Option<isize> {
+        match self {
+            BinOp::Add => a.checked_add(b),
+            BinOp::Sub => a.checked_sub(b),
+            BinOp::Mul => a.checked_mul(b),
+            BinOp::Eq => Some((a == b) as isize),
+            BinOp::Ne => Some((a != b) as isize),
+            BinOp::Lt => Some((a < b) as isize),
+            BinOp::Gt => Some((a > b) as isize),
+        }
+    }
+}
+
+/// Watch expression over the machine state, `x`, `x * cycle`, `y == 0`, comparisons give 0 or 1
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Expr {
+    lhs: Term,
+    rhs: Option<(BinOp, Term)>,
+}
+impl Expr {
+    fn eval(&self, handheld:&HandHeld) -> Result<isize, Day10Error> {
+        let term = |t:Term| match t {
+            Term::Register(r) => handheld.register(r),
+            Term::Cycle => handheld.cycle_counter,
+            Term::Pc => handheld.pc,
+            Term::Value(v) => v,
+        };
+        match self.rhs {
+            Some((op, rhs)) => op.apply(term(self.lhs), term(rhs)).ok_or(Day10Error::Overflow{cycle: handheld.cycle_counter}),
+            None => Ok(term(self.lhs)),
+        }
+    }
+}
+impl FromStr for Expr {
+    type Err = Day10Error;
+    fn from_str(v:&str) -> Result<Self, Day10Error> {
+        match v.split_whitespace().collect::<Vec<&str>>().as_slice() {
+            [lhs] => Ok(Expr{lhs: lhs.parse()?, rhs: None}),
+            [lhs, op, rhs] => {
+                let op = BinOp::ALL.iter().find(|(_, s)| s == op).ok_or(Day10Error::OpParseError)?.0;
+                Ok(Expr{lhs: lhs.parse()?, rhs: Some((op, rhs.parse()?))})
+            },
+            _ => Err(Day10Error::OpParseError),
+        }
+    }
+}
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.lhs)?;
+        if let Some((op, rhs)) = self.rhs {
+            write!(f, " {} {}", op.symbol(), rhs)?;
+        }
+        Ok(())
     }
 }
 
-fn day10(file_path:&str) -> Option<usize> {
-    let mut handheld = HandHeld::default();
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Breakpoint {
+    /// stop at the end of the cycle
+    Cycle(isize),
+    /// stop when the register changes to the value
+    Register(Register, isize),
+}
+impl FromStr for Breakpoint {
+    type Err = Day10Error;
+    fn from_str(v:&str) -> Result<Self, Day10Error> {
+        match v.split_whitespace().collect::<Vec<&str>>().as_slice() {
+            ["cycle", n] => n.parse().map(Breakpoint::Cycle).map_err(|_| Day10Error::OpParseError),
+            [r, n] => Ok(Breakpoint::Register(r.parse()?, n.parse().map_err(|_| Day10Error::OpParseError)?)),
+            _ => Err(Day10Error::OpParseError),
+        }
+    }
+}
 
-    let bf = BufReader::new(File::open(&file_path).expect(&file_path));
-    for line in bf.lines() {
-        match line {
-            Ok(op_input) => {
-                if let Ok(op) = op_input.parse::<Op>() {
-                    handheld.execute_op(op);
+/// Why the debugger handed control back
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Stop {
+    /// the requested cycle or instruction was done
+    Stepped,
+    /// index of the breakpoint hit
+    Breakpoint(usize),
+    /// the pc left the program
+    Halted,
+    /// ran for the cycle limit without stopping
+    Limit,
+}
+
+#[derive(Debug, Default)]
+struct Debugger {
+    breakpoints: Vec<Breakpoint>,
+    /// expressions and their last value, changes are recorded in the trace
+    watches: Vec<(Expr, isize)>,
+}
+impl Debugger {
+    fn watch(&mut self, expr:Expr, handheld:&HandHeld) -> Result<(), Day10Error> {
+        self.watches.push((expr, expr.eval(handheld)?));
+        Ok(())
+    }
+
+    fn step_cycle(&mut self, handheld:&mut HandHeld, program:&[Op]) -> Result<Stop, Day10Error> {
+        let before = handheld.registers;
+        match handheld.step_cycle(program) {
+            Ok(_) => {},
+            Err(Day10Error::Halted{..}) => return Ok(Stop::Halted),
+            Err(e) => return Err(e),
+        }
+        for (idx, (expr, value)) in self.watches.iter_mut().enumerate() {
+            let now = expr.eval(handheld)?;
+            if now != *value {
+                *value = now;
+                handheld.record(TraceEvent::Watch{watch: idx, value: now});
+            }
+        }
+        let hit = self.breakpoints.iter().position(|bp| match *bp {
+            Breakpoint::Cycle(cycle) => handheld.cycle_counter == cycle,
+            Breakpoint::Register(r, v) => before[r as usize] != v && handheld.register(r) == v,
+        });
+        Ok(hit.map_or(Stop::Stepped, Stop::Breakpoint))
+    }
+
+    /// Run to the end of the current or next instruction, stopping early on a breakpoint
+    fn step_instruction(&mut self, handheld:&mut HandHeld, program:&[Op]) -> Result<Stop, Day10Error> {
+        loop {
+            match self.step_cycle(handheld, program)? {
+                Stop::Stepped if handheld.in_flight.is_some() => continue,
+                stop => return Ok(stop),
+            }
+        }
+    }
+
+    /// Run until a breakpoint or the program halts, giving up after limit cycles
+    fn cont(&mut self, handheld:&mut HandHeld, program:&[Op], limit:usize) -> Result<Stop, Day10Error> {
+        for _ in 0..limit {
+            match self.step_cycle(handheld, program)? {
+                Stop::Stepped => continue,
+                stop => return Ok(stop),
+            }
+        }
+        Ok(Stop::Limit)
+    }
+}
+
+/// Interactive debugger on stdin
+fn debug_session(handheld:&mut HandHeld, program:&[Op]) {
+    let mut debugger = Debugger::default();
+    println!("s step cycle, n next instruction, c [<cycles>] continue, b cycle <n> | b <reg> <value> break, w <expr> watch, p [<expr>] print, q quit");
+    let mut line = String::new();
+    loop {
+        print!("{} pc {} > ", handheld, handheld.pc);
+        let _ = std::io::stdout().flush();
+        line.clear();
+        if std::io::stdin().read_line(&mut line).map_or(true, |n| n == 0) {
+            break;
+        }
+        let (cmd, arg) = line.trim().split_once(' ').unwrap_or((line.trim(), ""));
+        let stop = match cmd {
+            "s" => debugger.step_cycle(handheld, program),
+            "n" => debugger.step_instruction(handheld, program),
+            "c" => debugger.cont(handheld, program, arg.parse().unwrap_or(MAX_CYCLES)),
+            "b" => {
+                match arg.parse() {
+                    Ok(bp) => debugger.breakpoints.push(bp),
+                    Err(e) => println!("{}", e),
+                }
+                continue;
+            },
+            "w" => {
+                match arg.parse() {
+                    Ok(expr) => if let Err(e) = debugger.watch(expr, handheld) {
+                        println!("{}", e);
+                    },
+                    Err(e) => println!("{}", e),
                 }
+                continue;
             },
-            _ => {},
+            "p" if arg.is_empty() => {
+                println!("{:?}", handheld.registers);
+                continue;
+            },
+            "p" => {
+                match arg.parse::<Expr>() {
+                    Ok(expr) => match expr.eval(handheld) {
+                        Ok(value) => println!("{} = {}", expr, value),
+                        Err(e) => println!("{}", e),
+                    },
+                    Err(e) => println!("{}", e),
+                }
+                continue;
+            },
+            "q" => break,
+            _ => continue,
+        };
+        match stop {
+            Ok(Stop::Breakpoint(idx)) => println!("breakpoint {} {:?}", idx, debugger.breakpoints[idx]),
+            Ok(Stop::Halted) => println!("halted"),
+            Ok(Stop::Limit) => println!("still running after the cycle limit"),
+            Ok(Stop::Stepped) => {},
+            Err(e) => println!("{}", e),
+        }
+        for (expr, value) in &debugger.watches {
+            println!("  {} = {}", expr, value);
         }
     }
-    Some(handheld.signal_strenth_nvram.iter().fold(0, |f,v| f+v) as usize)
+}
+
+fn day10(file_path:&str, debug:bool, trace:Option<&str>, crt:(usize, usize), sampling:Sampling, max_cycles:usize) -> Result<(Vec<Sample>, Framebuffer), Day10Error> {
+    let program = assemble(BufReader::new(File::open(file_path).expect(file_path)))?;
+    let mut handheld = HandHeld{sampling, ..HandHeld::with_crt(crt.0, crt.1)};
+    if trace.is_some() {
+        handheld.trace = Some(Vec::new());
+    }
+    if debug {
+        debug_session(&mut handheld, &program);
+    } else {
+        handheld.run(&program, max_cycles)?;
+    }
+    if let (Some(path), Some(records)) = (trace, &handheld.trace) {
+        let mut out = File::create(path).map_err(|_| Day10Error::Io)?;
+        for record in records {
+            writeln!(out, "{}", record.to_json()).map_err(|_| Day10Error::Io)?;
+        }
+    }
+    Ok((handheld.samples().collect(), handheld.framebuffer()))
 }
 
 fn main()
 {
     let args:Vec<String> = env::args().collect();
-    if let None = args.get(1) {
-        println!("Usage: {} <path>", args[0]);
+    let usage = || {
+        println!("Usage: {} <path> [--debug] [--trace <file.jsonl>] [--crt <width>x<height>] [--display ascii|ansi|pbm [<file>]]
+    [--sample <first>+<period> | <cycle>,<cycle>...] [--after] [--reduce sum|max|min|count|last] [--samples] [--max-cycles <n>]", args[0]);
         std::process::exit(1);
+    };
+    if args.get(1).is_none() {
+        usage();
+    }
+    let option = |name:&str, offset:usize| {
+        args.iter().position(|a| a == name).and_then(|pos| args.get(pos + offset)).filter(|v| !v.starts_with("--"))
+    };
+    let flag = |name:&str| args.iter().skip(2).any(|a| a == name);
+    let debug = flag("--debug");
+    let trace = option("--trace", 1);
+    let crt = match option("--crt", 1).map(|crt| crt.split_once('x')) {
+        None => (CRT_WIDTH, CRT_HEIGHT),
+        Some(Some((w, h))) => match (w.parse(), h.parse()) {
+            (Ok(w), Ok(h)) if w > 0 && h > 0 => (w, h),
+            _ => return usage(),
+        },
+        Some(None) => return usage(),
+    };
+    let display:Box<dyn CrtDisplay> = match option("--display", 1).map(|d| d.as_str()) {
+        None | Some("ascii") => Box::new(AsciiDisplay),
+        Some("ansi") => Box::new(AnsiDisplay),
+        Some("pbm") => Box::new(PbmDisplay),
+        _ => return usage(),
+    };
+    let schedule = match option("--sample", 1).map(|s| s.parse()) {
+        None => Sampling::default().schedule,
+        Some(Ok(schedule)) => schedule,
+        Some(Err(_)) => return usage(),
+    };
+    let sampling = Sampling{schedule, phase: if flag("--after") {SamplePhase::After} else {SamplePhase::During}};
+    let reducer = match option("--reduce", 1).map(|r| r.parse()) {
+        None => Reducer::Sum,
+        Some(Ok(reducer)) => reducer,
+        Some(Err(_)) => return usage(),
+    };
+    let max_cycles = match option("--max-cycles", 1).map(|m| m.parse()) {
+        None => MAX_CYCLES,
+        Some(Ok(max_cycles)) => max_cycles,
+        Some(Err(_)) => return usage(),
+    };
+    match day10(&args[1], debug, trace.map(|t| t.as_str()), crt, sampling, max_cycles) {
+        Ok((samples, frame)) => {
+            if flag("--samples") {
+                for (cycle, x, strength) in &samples {
+                    println!("cycle {} x {} strength {}", cycle, x, strength);
+                }
+            }
+            println!("signal strength {:?} {:?}", reducer, reducer.reduce(samples.into_iter()));
+            match ocr(&frame) {
+                Ok(text) => println!("crt reads {}", text),
+                Err(e) => println!("crt unreadable: {:?}", e),
+            }
+            let drawn = match option("--display", 2) {
+                Some(path) => File::create(path).and_then(|mut f| display.draw(&frame, &mut f)),
+                None => display.draw(&frame, &mut std::io::stdout()),
+            };
+            if let Err(e) = drawn {
+                println!("{}", e);
+                std::process::exit(1);
+            }
+        },
+        Err(e) => {
+            println!("{:?}", e);
+            std::process::exit(1);
+        },
     }
-    dbg!(day10(&args[1]));
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{Day10Error,HandHeld,Op};
+    use super::{assemble, ocr, AsciiDisplay, Breakpoint, CrtDisplay, Framebuffer, PbmDisplay, FONT,
+        Reducer, SamplePhase, Sampling, Schedule, Day10Error, Debugger, Expr, HandHeld, Op, Operand, OperandKind, Register, Stop, TraceEvent, ISA};
+    use std::io::BufReader;
 
     #[test]
     fn test_op() {
-        assert_eq!("noop".parse::<Op>(), Ok(Op::NOOP));
-        assert_eq!("addx 1".parse::<Op>(), Ok(Op::ADDX(1)));
-        assert_eq!("addx -5".parse::<Op>(), Ok(Op::ADDX(-5)));
+        assert_eq!("noop".parse::<Op>(), Ok(Op::Noop));
+        assert_eq!("addx 1".parse::<Op>(), Ok(Op::Addx(1)));
+        assert_eq!("addx -5".parse::<Op>(), Ok(Op::Addx(-5)));
         assert_eq!("addx addx".parse::<Op>(), Err(Day10Error::OpParseError));
+        assert_eq!("addx 1 2".parse::<Op>(), Err(Day10Error::OpParseError));
+        assert_eq!("jnz y -3".parse::<Op>(), Ok(Op::Jnz(Register::Y, -3)));
+        assert_eq!("jz z +1".parse::<Op>(), Err(Day10Error::OpParseError));
+        assert_eq!("jmp loop".parse::<Op>(), Err(Day10Error::OpParseError));
+        for op in [Op::Noop, Op::Addx(-5), Op::Mulx(3), Op::Jmp(2), Op::Jz(Register::X, -1)] {
+            assert_eq!(op.to_string().parse::<Op>(), Ok(op));
+        }
+        // every entry in the table decodes to an op that maps back to it
+        for spec in ISA.iter() {
+            let operands:Vec<Operand> = spec.operands.iter().map(|kind| match kind {
+                OperandKind::Register => Operand::Register(Register::Y),
+                OperandKind::Immediate => Operand::Immediate(7),
+                OperandKind::Target => Operand::Target(-2),
+            }).collect();
+            let op = (spec.decode)(&operands).unwrap();
+            assert_eq!(op.opcode(), spec.opcode);
+            assert_eq!(op.operands(), operands);
+            let extra:Vec<Operand> = operands.iter().copied().chain([Operand::Immediate(1)]).collect();
+            assert_eq!((spec.decode)(&extra), None);
+        }
     }
 
     #[test]
     fn test_handheld() {
         let mut handheld = HandHeld::default();
         assert_eq!(handheld.cycle_counter, 0);
-        assert_eq!(handheld.register_x, 1);
+        assert_eq!(handheld.register(Register::X), 1);
         assert_eq!(handheld.crt_buffer, vec![]);
         assert_eq!(handheld.signal_strenth_nvram, vec![]);
 
-        handheld.execute_op(Op::NOOP);
-        assert_eq!(handheld.register_x, 1);
+        assert_eq!(handheld.execute_op(Op::Noop), Ok(()));
+        assert_eq!(handheld.register(Register::X), 1);
         assert_eq!(handheld.cycle_counter, 1);
         assert_eq!(handheld.crt_buffer, vec!['#']);
         assert_eq!(handheld.signal_strenth_nvram, vec![]);
 
-        handheld.execute_op(Op::ADDX(6));
-        assert_eq!(handheld.register_x, 7);
+        assert_eq!(handheld.execute_op(Op::Addx(6)), Ok(()));
+        assert_eq!(handheld.register(Register::X), 7);
         assert_eq!(handheld.cycle_counter, 3);
         assert_eq!(handheld.crt_buffer, vec!['#', '#', '#']);
         assert_eq!(handheld.signal_strenth_nvram, vec![]);
 
-        handheld.execute_op(Op::ADDX(10));
-        assert_eq!(handheld.register_x, 17);
+        assert_eq!(handheld.execute_op(Op::Addx(10)), Ok(()));
+        assert_eq!(handheld.register(Register::X), 17);
         assert_eq!(handheld.cycle_counter, 5);
         assert_eq!(handheld.crt_buffer, vec!['#', '#', '#', '.', '.']);
         assert_eq!(handheld.signal_strenth_nvram, vec![]);
+
+        // overflow is an error and leaves the registers alone
+        assert_eq!(handheld.execute_op(Op::Mulx(isize::MAX)), Err(Day10Error::Overflow{cycle: 8}));
+        assert_eq!(handheld.register(Register::X), 17);
+        let mut handheld = HandHeld{registers: [isize::MIN, 0], ..HandHeld::default()};
+        assert_eq!(handheld.execute_op(Op::Addx(-1)), Err(Day10Error::Overflow{cycle: 2}));
+        handheld.pc = 1;
+        assert_eq!(handheld.execute_op(Op::Jmp(isize::MAX)), Err(Day10Error::Overflow{cycle: 3}));
+        assert_eq!(handheld.pc, 1);
+        let sampling = Sampling{schedule: Schedule::Cycles(vec![2]), phase: SamplePhase::During};
+        let mut handheld = HandHeld{registers: [isize::MAX, 0], sampling, ..HandHeld::default()};
+        assert_eq!(handheld.execute_op(Op::Noop), Ok(()));
+        assert_eq!(handheld.execute_op(Op::Noop), Err(Day10Error::Overflow{cycle: 2}));
+
+        // a program that never halts stops at the limit, before starting an op it can't finish
+        let mut handheld = HandHeld::default();
+        assert_eq!(handheld.run(&[Op::Addx(1), Op::Jmp(-1)], 10), Err(Day10Error::CycleLimit{limit: 10}));
+        assert_eq!((handheld.cycle_counter, handheld.register(Register::X)), (9, 4));
+        assert_eq!(HandHeld::default().run(&[Op::Addx(1), Op::Noop], 3), Ok(()));
+    }
+
+    #[test]
+    fn test_assemble() {
+        let source = concat!(
+            "; x = 2 * 3 + 1 by repeated addition\n",
+            "    addy 3\n",
+            "loop: addx 2   ; once per y\n",
+            "    addy -1\n",
+            "    jnz y loop\n",
+            "    jmp end\n",
+            "    mulx 100 ; skipped\n",
+            "end:\n",
+        );
+        let program = assemble(BufReader::new(source.as_bytes())).unwrap();
+        assert_eq!(program, vec![Op::Addy(3), Op::Addx(2), Op::Addy(-1), Op::Jnz(Register::Y, -2), Op::Jmp(2), Op::Mulx(100)]);
+
+        let mut handheld = HandHeld::default();
+        assert_eq!(handheld.run(&program, 100), Ok(()));
+        assert_eq!(handheld.register(Register::X), 7);
+        assert_eq!(handheld.register(Register::Y), 0);
+        assert_eq!(handheld.cycle_counter, 2 + 3 * (2 + 2 + 2) + 1);
+
+        assert_eq!(assemble(BufReader::new("a:\nnoop\na: noop\n".as_bytes())), Err(Day10Error::DuplicateLabel{line: 3, label: "a".to_string()}));
+        assert_eq!(assemble(BufReader::new("noop\njz x nowhere\n".as_bytes())), Err(Day10Error::UnknownLabel{line: 2, label: "nowhere".to_string()}));
+        assert_eq!(assemble(BufReader::new("noop\nnoop 1\n".as_bytes())), Err(Day10Error::InvalidOp{line: 2}));
+    }
+
+    #[test]
+    fn test_debugger() {
+        let program = vec![Op::Noop, Op::Addx(3), Op::Addx(-5)];
+        let mut handheld = HandHeld{trace: Some(Vec::new()), ..HandHeld::default()};
+        let mut debugger = Debugger::default();
+        debugger.breakpoints.push("x -1".parse().unwrap());
+        debugger.breakpoints.push(Breakpoint::Cycle(2));
+        assert_eq!("cycle".parse::<Breakpoint>(), Err(Day10Error::OpParseError));
+        assert_eq!(debugger.watch("x * cycle".parse().unwrap(), &handheld), Ok(()));
+
+        assert_eq!(debugger.step_cycle(&mut handheld, &program), Ok(Stop::Stepped));
+        assert_eq!((handheld.cycle_counter, handheld.pc), (1, 1));
+        // stops in the middle of the addx
+        assert_eq!(debugger.cont(&mut handheld, &program, 100), Ok(Stop::Breakpoint(1)));
+        assert_eq!((handheld.cycle_counter, handheld.register(Register::X)), (2, 1));
+        assert_eq!(debugger.step_instruction(&mut handheld, &program), Ok(Stop::Stepped));
+        assert_eq!((handheld.cycle_counter, handheld.register(Register::X)), (3, 4));
+        assert_eq!(debugger.cont(&mut handheld, &program, 100), Ok(Stop::Breakpoint(0)));
+        assert_eq!(handheld.cycle_counter, 5);
+        assert_eq!(debugger.cont(&mut handheld, &program, 100), Ok(Stop::Halted));
+        assert_eq!(handheld.step_cycle(&program), Err(Day10Error::Halted{pc: 3}));
+
+        let watched:Vec<isize> = handheld.trace.as_ref().unwrap().iter().filter_map(|r| match r.event {
+            TraceEvent::Watch{value, ..} => Some(value),
+            _ => None,
+        }).collect();
+        assert_eq!(watched, vec![1, 2, 12, 16, -5]);
+        assert_eq!(handheld.trace.as_ref().unwrap()[0].to_json(), r#"{"cycle":0,"pc":0,"x":1,"y":0,"event":"fetch","op":"noop"}"#);
+
+        // a program that never halts gives up at the limit
+        let program = vec![Op::Jmp(0)];
+        let mut handheld = HandHeld::default();
+        assert_eq!(Debugger::default().cont(&mut handheld, &program, 10), Ok(Stop::Limit));
+        assert_eq!(handheld.cycle_counter, 10);
+
+        // watches report overflow instead of wrapping
+        let mut debugger = Debugger::default();
+        handheld.registers[Register::X as usize] = 2;
+        let expr:Expr = "x * 9223372036854775807".parse().unwrap();
+        assert_eq!(expr.eval(&handheld), Err(Day10Error::Overflow{cycle: 10}));
+        assert_eq!(debugger.watch(expr, &handheld), Err(Day10Error::Overflow{cycle: 10}));
+        assert_eq!(debugger.watch("x - 1".parse().unwrap(), &handheld), Ok(()));
+        assert_eq!(debugger.watches, vec![("x - 1".parse().unwrap(), 1)]);
+    }
+
+    #[test]
+    fn test_display() {
+        let program = vec![Op::Addx(2), Op::Noop, Op::Addx(3), Op::Noop, Op::Noop, Op::Addx(2), Op::Noop, Op::Noop, Op::Noop, Op::Noop];
+        let mut handheld = HandHeld::with_crt(6, 2);
+        assert_eq!(handheld.run(&program, 100), Ok(()));
+        assert_eq!(handheld.cycle_counter, 13);
+        // the last cycle wrapped back to the top left, after the sprite had moved off screen
+        assert_eq!(handheld.crt_buffer.iter().collect::<String>(), ".#####......");
+
+        let frame = handheld.framebuffer();
+        let mut out = Vec::new();
+        AsciiDisplay.draw(&frame, &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), ".#####\n......\n");
+        let mut out = Vec::new();
+        PbmDisplay.draw(&frame, &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "P1\n6 2\n0 1 1 1 1 1\n0 0 0 0 0 0\n");
+    }
+
+    #[test]
+    fn test_ocr() {
+        // every letter of the font, with the spacing column after each
+        let letters:String = FONT.iter().map(|(letter, _)| letter).collect();
+        let rows:Vec<String> = (0..6).map(|row| FONT.iter().map(|(_, rows)| rows[row]).collect::<Vec<&str>>().join(".")).collect();
+        let width = rows[0].len();
+        let pixels:Vec<bool> = rows.iter().flat_map(|row| row.chars()).map(|c| c == '#').collect();
+        let mut frame = Framebuffer{width, height: 6, pixels};
+        assert_eq!(ocr(&frame), Ok(letters));
+
+        frame.pixels[0] = true;
+        frame.pixels[5 * width + 13] = true;
+        assert_eq!(ocr(&frame), Err(Day10Error::UnrecognisedGlyphs(vec![0, 2])));
+        assert_eq!(ocr(&Framebuffer{width: 4, height: 5, pixels: vec![false; 20]}), Err(Day10Error::FrameSize{width: 4, height: 5}));
+    }
+
+    #[test]
+    fn test_sampling() {
+        let program = vec![Op::Noop, Op::Addx(3), Op::Addx(-5), Op::Addx(10)];
+        let sampling = Sampling{schedule: "1+2".parse().unwrap(), phase: SamplePhase::During};
+        let mut handheld = HandHeld{sampling, ..HandHeld::default()};
+        assert_eq!(handheld.run(&program, 100), Ok(()));
+        assert_eq!(handheld.samples().collect::<Vec<_>>(), vec![(1, 1, 1), (3, 1, 3), (5, 4, 20), (7, -1, -7)]);
+        assert_eq!(Reducer::Sum.reduce(handheld.samples()), Some(17));
+        assert_eq!(Reducer::Min.reduce(handheld.samples()), Some(-7));
+        assert_eq!(Reducer::Count.reduce(handheld.samples()), Some(4));
+
+        let sampling = Sampling{schedule: "2,3,5,7".parse().unwrap(), phase: SamplePhase::After};
+        let mut handheld = HandHeld{sampling, ..HandHeld::default()};
+        assert_eq!(handheld.run(&program, 100), Ok(()));
+        assert_eq!(handheld.samples().collect::<Vec<_>>(), vec![(2, 1, 2), (3, 4, 12), (5, -1, -5), (7, 9, 63)]);
+        assert_eq!(Reducer::Max.reduce(handheld.samples()), Some(63));
+        assert_eq!(Reducer::Max.reduce(std::iter::empty()), None);
+        assert_eq!(Reducer::Sum.reduce([(1, 1, isize::MAX), (2, 1, 1)].iter().copied()), None);
+        assert_eq!(Reducer::Sum.reduce([(1, 1, isize::MAX), (2, 1, -1)].iter().copied()), Some(isize::MAX - 1));
+        assert_eq!("20+0".parse::<Schedule>(), Err(Day10Error::OpParseError));
     }
 }
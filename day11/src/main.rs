@@ -29,123 +29,658 @@ Monkey 3:
     If false: throw to monkey 1
 
 */
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
 use std::env;
 use std::fmt;
+use std::hash::{Hash, Hasher};
 use std::fs::read_to_string;
+use std::rc::Rc;
 use std::str::FromStr;
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq, Eq)]
 enum Day11Error {
-    MonkeyParseError,
+    Io,
+    NoMonkeys,
+    /// blocks and lines count from 1
+    Parse { block: usize, line: usize, expected: &'static str, found: String },
+    /// monkeys must be numbered 0, 1, 2... in order
+    NonContiguousId { block: usize, line: usize, expected: usize, found: usize },
+    /// throws to a monkey that does not exist
+    UnknownMonkey { block: usize, line: usize, monkey: usize },
+    /// the operation text that could not be parsed
+    InvalidOperation(String),
+    /// a monkey's worry arithmetic failed
+    Worry { monkey: usize, err: WorryErr },
+    /// the top inspection counts multiply past u128
+    MonkeyBusinessOverflow,
+    /// the product of every monkey's test does not fit in a u64
+    ModulusOverflow,
+    /// no state repeated within this many rounds
+    NoCycle { limit: usize },
 }
 
-#[derive(Debug, PartialEq, Eq)]
-struct Item {
-    worry_level: usize,
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum WorryErr {
+    Overflow,
+    /// went below zero
+    Underflow,
+    DivideByZero,
+    /// the representation cannot do this, like dividing residues
+    Unsupported,
+}
+
+/// Worry level arithmetic, failing rather than wrapping
+trait Worry: Sized + Clone + PartialEq + fmt::Debug + fmt::Display {
+    /// shared by every value, the moduli for residues
+    type Context;
+    fn from_u64(v:u64, ctx:&Self::Context) -> Self;
+    /// an operation constant in the same context as self
+    fn constant(&self, v:u64) -> Self;
+    fn try_add(&self, rhs:&Self) -> Result<Self, WorryErr>;
+    fn try_sub(&self, rhs:&Self) -> Result<Self, WorryErr>;
+    fn try_mul(&self, rhs:&Self) -> Result<Self, WorryErr>;
+    fn try_div(&self, rhs:&Self) -> Result<Self, WorryErr>;
+    /// remainder for the divisibility test
+    fn rem_u64(&self, m:u64) -> Result<u64, WorryErr>;
+    /// reduce modulo m, keeping every remainder that divides m
+    fn modulo(&self, m:u64) -> Result<Self, WorryErr>;
+}
+
+macro_rules! checked_worry {
+    ($t:ty) => {
+        impl Worry for $t {
+            type Context = ();
+            fn from_u64(v:u64, _:&()) -> Self {
+                v as $t
+            }
+            fn constant(&self, v:u64) -> Self {
+                v as $t
+            }
+            fn try_add(&self, rhs:&Self) -> Result<Self, WorryErr> {
+                self.checked_add(*rhs).ok_or(WorryErr::Overflow)
+            }
+            fn try_sub(&self, rhs:&Self) -> Result<Self, WorryErr> {
+                self.checked_sub(*rhs).ok_or(WorryErr::Underflow)
+            }
+            fn try_mul(&self, rhs:&Self) -> Result<Self, WorryErr> {
+                self.checked_mul(*rhs).ok_or(WorryErr::Overflow)
+            }
+            fn try_div(&self, rhs:&Self) -> Result<Self, WorryErr> {
+                self.checked_div(*rhs).ok_or(WorryErr::DivideByZero)
+            }
+            fn rem_u64(&self, m:u64) -> Result<u64, WorryErr> {
+                self.checked_rem(m as $t).map(|r| r as u64).ok_or(WorryErr::DivideByZero)
+            }
+            fn modulo(&self, m:u64) -> Result<Self, WorryErr> {
+                self.checked_rem(m as $t).ok_or(WorryErr::DivideByZero)
+            }
+        }
+    };
+}
+checked_worry!(u64);
+checked_worry!(u128);
+
+/// Unbounded unsigned integer, base 2^32 limbs least significant first with no zero high limbs
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+struct BigUint {
+    limbs: Vec<u32>,
+}
+impl BigUint {
+    fn new(v:u64) -> Self {
+        Self{limbs: vec![v as u32, (v >> 32) as u32]}.normalized()
+    }
+
+    fn normalized(mut self) -> Self {
+        while self.limbs.last() == Some(&0) {
+            self.limbs.pop();
+        }
+        self
+    }
+
+    fn is_zero(&self) -> bool {
+        self.limbs.is_empty()
+    }
+
+    fn bits(&self) -> usize {
+        self.limbs.last().map_or(0, |top| self.limbs.len() * 32 - top.leading_zeros() as usize)
+    }
+
+    fn bit(&self, idx:usize) -> bool {
+        self.limbs.get(idx / 32).is_some_and(|limb| limb >> (idx % 32) & 1 == 1)
+    }
+
+    fn add(&self, rhs:&Self) -> Self {
+        let mut limbs = Vec::with_capacity(self.limbs.len().max(rhs.limbs.len()) + 1);
+        let mut carry = 0u64;
+        for idx in 0..self.limbs.len().max(rhs.limbs.len()) {
+            let sum = *self.limbs.get(idx).unwrap_or(&0) as u64 + *rhs.limbs.get(idx).unwrap_or(&0) as u64 + carry;
+            limbs.push(sum as u32);
+            carry = sum >> 32;
+        }
+        limbs.push(carry as u32);
+        Self{limbs}.normalized()
+    }
+
+    fn sub(&self, rhs:&Self) -> Option<Self> {
+        let mut limbs = Vec::with_capacity(self.limbs.len());
+        let mut borrow = 0i64;
+        for idx in 0..self.limbs.len().max(rhs.limbs.len()) {
+            let mut diff = *self.limbs.get(idx).unwrap_or(&0) as i64 - *rhs.limbs.get(idx).unwrap_or(&0) as i64 - borrow;
+            borrow = (diff < 0) as i64;
+            diff += borrow << 32;
+            limbs.push(diff as u32);
+        }
+        if borrow != 0 {
+            return None;
+        }
+        Some(Self{limbs}.normalized())
+    }
+
+    fn mul(&self, rhs:&Self) -> Self {
+        let mut limbs = vec![0u32; self.limbs.len() + rhs.limbs.len()];
+        for (i, &a) in self.limbs.iter().enumerate() {
+            let mut carry = 0u64;
+            for (j, &b) in rhs.limbs.iter().enumerate() {
+                let product = a as u64 * b as u64 + limbs[i + j] as u64 + carry;
+                limbs[i + j] = product as u32;
+                carry = product >> 32;
+            }
+            limbs[i + rhs.limbs.len()] = carry as u32;
+        }
+        Self{limbs}.normalized()
+    }
+
+    /// quotient and remainder for a divisor that fits a u64
+    fn div_rem_u64(&self, d:u64) -> (Self, u64) {
+        let mut limbs = vec![0u32; self.limbs.len()];
+        let mut rem = 0u128;
+        for (idx, &limb) in self.limbs.iter().enumerate().rev() {
+            let cur = rem << 32 | limb as u128;
+            limbs[idx] = (cur / d as u128) as u32;
+            rem = cur % d as u128;
+        }
+        (Self{limbs}.normalized(), rem as u64)
+    }
+
+    /// shift and subtract long division, for divisors wider than a u64
+    fn div_rem(&self, d:&Self) -> (Self, Self) {
+        let mut quotient = Self{limbs: vec![0; self.limbs.len()]};
+        let mut rem = Self{limbs: Vec::new()};
+        for idx in (0..self.bits()).rev() {
+            rem = rem.add(&rem);
+            if self.bit(idx) {
+                rem = rem.add(&Self::new(1));
+            }
+            if let Some(r) = rem.sub(d) {
+                rem = r;
+                quotient.limbs[idx / 32] |= 1 << (idx % 32);
+            }
+        }
+        (quotient.normalized(), rem)
+    }
+}
+impl fmt::Display for BigUint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // peel off nine decimal digits at a time
+        let mut chunks = Vec::new();
+        let mut v = self.clone();
+        while !v.is_zero() {
+            let (q, r) = v.div_rem_u64(1_000_000_000);
+            chunks.push(r);
+            v = q;
+        }
+        match chunks.split_last() {
+            None => write!(f, "0"),
+            Some((top, rest)) => {
+                write!(f, "{}", top)?;
+                rest.iter().rev().try_for_each(|chunk| write!(f, "{:09}", chunk))
+            },
+        }
+    }
+}
+impl Worry for BigUint {
+    type Context = ();
+    fn from_u64(v:u64, _:&()) -> Self {
+        BigUint::new(v)
+    }
+    fn constant(&self, v:u64) -> Self {
+        BigUint::new(v)
+    }
+    fn try_add(&self, rhs:&Self) -> Result<Self, WorryErr> {
+        Ok(self.add(rhs))
+    }
+    fn try_sub(&self, rhs:&Self) -> Result<Self, WorryErr> {
+        self.sub(rhs).ok_or(WorryErr::Underflow)
+    }
+    fn try_mul(&self, rhs:&Self) -> Result<Self, WorryErr> {
+        Ok(self.mul(rhs))
+    }
+    fn try_div(&self, rhs:&Self) -> Result<Self, WorryErr> {
+        match rhs.limbs.len() {
+            0 => Err(WorryErr::DivideByZero),
+            1 | 2 => Ok(self.div_rem_u64(rhs.limbs.iter().rev().fold(0, |v, &limb| v << 32 | limb as u64)).0),
+            _ => Ok(self.div_rem(rhs).0),
+        }
+    }
+    fn rem_u64(&self, m:u64) -> Result<u64, WorryErr> {
+        match m {
+            0 => Err(WorryErr::DivideByZero),
+            _ => Ok(self.div_rem_u64(m).1),
+        }
+    }
+    fn modulo(&self, m:u64) -> Result<Self, WorryErr> {
+        self.rem_u64(m).map(BigUint::new)
+    }
+}
+
+/// Residue number system: only the remainders for each modulus are kept, enough for the
+/// monkeys' divisibility tests while adding and multiplying. Subtraction cannot notice
+/// going below zero and division is not possible.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+struct Residues {
+    moduli: Rc<Vec<u64>>,
+    values: Vec<u64>,
+}
+impl Residues {
+    fn zip_with<F: Fn(u128, u128, u128) -> u128>(&self, rhs:&Self, f:F) -> Self {
+        let values = self.moduli.iter().zip(self.values.iter().zip(&rhs.values))
+            .map(|(&m, (&a, &b))| (f(a as u128, b as u128, m as u128) % m as u128) as u64).collect();
+        Self{moduli: Rc::clone(&self.moduli), values}
+    }
+}
+impl fmt::Display for Residues {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let residues:Vec<String> = self.values.iter().zip(self.moduli.iter()).map(|(v, m)| format!("{}%{}", v, m)).collect();
+        write!(f, "({})", residues.join(" "))
+    }
+}
+impl Worry for Residues {
+    type Context = Rc<Vec<u64>>;
+    fn from_u64(v:u64, moduli:&Rc<Vec<u64>>) -> Self {
+        Self{moduli: Rc::clone(moduli), values: moduli.iter().map(|m| v % m).collect()}
+    }
+    fn constant(&self, v:u64) -> Self {
+        Self::from_u64(v, &self.moduli)
+    }
+    fn try_add(&self, rhs:&Self) -> Result<Self, WorryErr> {
+        Ok(self.zip_with(rhs, |a, b, _| a + b))
+    }
+    fn try_sub(&self, rhs:&Self) -> Result<Self, WorryErr> {
+        Ok(self.zip_with(rhs, |a, b, m| a + m - b))
+    }
+    fn try_mul(&self, rhs:&Self) -> Result<Self, WorryErr> {
+        Ok(self.zip_with(rhs, |a, b, _| a * b))
+    }
+    fn try_div(&self, _:&Self) -> Result<Self, WorryErr> {
+        Err(WorryErr::Unsupported)
+    }
+    fn rem_u64(&self, m:u64) -> Result<u64, WorryErr> {
+        match self.moduli.iter().position(|&modulus| m != 0 && modulus.is_multiple_of(m)) {
+            Some(idx) => Ok(self.values[idx] % m),
+            None => Err(WorryErr::Unsupported),
+        }
+    }
+    fn modulo(&self, m:u64) -> Result<Self, WorryErr> {
+        // already reduced by every modulus that divides m
+        match self.moduli.iter().all(|&modulus| modulus != 0 && m.is_multiple_of(modulus)) {
+            true => Ok(self.clone()),
+            false => Err(WorryErr::Unsupported),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operator {
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+impl Operator {
+    const ALL:[Operator; 4] = [Operator::Add, Operator::Sub, Operator::Mul, Operator::Div];
+
+    fn symbol(self) -> char {
+        match self {
+            Operator::Add => '+',
+            Operator::Sub => '-',
+            Operator::Mul => '*',
+            Operator::Div => '/',
+        }
+    }
+
+    fn precedence(self) -> u8 {
+        match self {
+            Operator::Add | Operator::Sub => 1,
+            Operator::Mul | Operator::Div => 2,
+        }
+    }
+}
+
+/// Right hand side of `new = ...`
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Expr {
+    Old,
+    Const(u64),
+    BinOp(Box<Expr>, Operator, Box<Expr>),
+}
+impl Expr {
+    /// New worry level
+    fn eval<W: Worry>(&self, old:&W) -> Result<W, WorryErr> {
+        match self {
+            Expr::Old => Ok(old.clone()),
+            Expr::Const(v) => Ok(old.constant(*v)),
+            Expr::BinOp(lhs, op, rhs) => {
+                let (lhs, rhs) = (lhs.eval(old)?, rhs.eval(old)?);
+                match op {
+                    Operator::Add => lhs.try_add(&rhs),
+                    Operator::Sub => lhs.try_sub(&rhs),
+                    Operator::Mul => lhs.try_mul(&rhs),
+                    Operator::Div => lhs.try_div(&rhs),
+                }
+            },
+        }
+    }
+
+    /// dividing by a literal 0 is always an error
+    fn has_zero_divisor(&self) -> bool {
+        match self {
+            Expr::BinOp(_, Operator::Div, rhs) if **rhs == Expr::Const(0) => true,
+            Expr::BinOp(lhs, _, rhs) => lhs.has_zero_divisor() || rhs.has_zero_divisor(),
+            _ => false,
+        }
+    }
+
+    fn precedence(&self) -> u8 {
+        match self {
+            Expr::BinOp(_, op, _) => op.precedence(),
+            _ => u8::MAX,
+        }
+    }
+}
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expr::Old => write!(f, "old"),
+            Expr::Const(v) => write!(f, "{}", v),
+            Expr::BinOp(lhs, op, rhs) => {
+                // operators are left associative, so an equal precedence rhs needs brackets
+                if lhs.precedence() < op.precedence() {
+                    write!(f, "({})", lhs)?;
+                } else {
+                    write!(f, "{}", lhs)?;
+                }
+                write!(f, " {} ", op.symbol())?;
+                if rhs.precedence() <= op.precedence() {
+                    write!(f, "({})", rhs)
+                } else {
+                    write!(f, "{}", rhs)
+                }
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Old,
+    Number(u64),
+    Operator(Operator),
+    Open,
+    Close,
+}
+
+/// Recursive descent over `expr := term (+|- term)*`, `term := atom (*|/ atom)*`,
+/// `atom := old | number | ( expr )`
+struct ExprParser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+impl ExprParser {
+    fn tokenize(v:&str) -> Option<Vec<Token>> {
+        let mut tokens = Vec::new();
+        let mut chars = v.chars().peekable();
+        while let Some(&c) = chars.peek() {
+            match c {
+                c if c.is_whitespace() => { chars.next(); },
+                '+' | '-' | '*' | '/' => {
+                    chars.next();
+                    let op = Operator::ALL.iter().copied().find(|op| op.symbol() == c)?;
+                    tokens.push(Token::Operator(op));
+                },
+                '(' => { chars.next(); tokens.push(Token::Open); },
+                ')' => { chars.next(); tokens.push(Token::Close); },
+                '0'..='9' => {
+                    let mut digits = String::new();
+                    while let Some(d) = chars.next_if(|d| d.is_ascii_digit()) {
+                        digits.push(d);
+                    }
+                    tokens.push(Token::Number(digits.parse().ok()?));
+                },
+                _ => {
+                    let mut word = String::new();
+                    while let Some(w) = chars.next_if(|w| w.is_ascii_alphabetic()) {
+                        word.push(w);
+                    }
+                    if word != "old" {
+                        return None;
+                    }
+                    tokens.push(Token::Old);
+                },
+            }
+        }
+        Some(tokens)
+    }
+
+    fn next_operator(&mut self, precedence:u8) -> Option<Operator> {
+        match self.tokens.get(self.pos) {
+            Some(Token::Operator(op)) if op.precedence() == precedence => {
+                self.pos += 1;
+                Some(*op)
+            },
+            _ => None,
+        }
+    }
+
+    fn binary(&mut self, precedence:u8) -> Option<Expr> {
+        let operand = |parser:&mut ExprParser| if precedence == 1 {parser.binary(2)} else {parser.atom()};
+        let mut expr = operand(self)?;
+        while let Some(op) = self.next_operator(precedence) {
+            expr = Expr::BinOp(Box::new(expr), op, Box::new(operand(self)?));
+        }
+        Some(expr)
+    }
+
+    fn atom(&mut self) -> Option<Expr> {
+        let token = self.tokens.get(self.pos)?.clone();
+        self.pos += 1;
+        match token {
+            Token::Old => Some(Expr::Old),
+            Token::Number(v) => Some(Expr::Const(v)),
+            Token::Open => {
+                let expr = self.binary(1)?;
+                if self.tokens.get(self.pos) != Some(&Token::Close) {
+                    return None;
+                }
+                self.pos += 1;
+                Some(expr)
+            },
+            _ => None,
+        }
+    }
+}
+impl FromStr for Expr {
+    type Err = Day11Error;
+    fn from_str(v:&str) -> Result<Self, Day11Error> {
+        let err = || Day11Error::InvalidOperation(v.trim().to_string());
+        let mut parser = ExprParser{tokens: ExprParser::tokenize(v).ok_or_else(err)?, pos: 0};
+        let expr = parser.binary(1).ok_or_else(err)?;
+        if parser.pos != parser.tokens.len() || expr.has_zero_divisor() {
+            return Err(err());
+        }
+        Ok(expr)
+    }
 }
-impl Item {
-    fn new(worry_level:usize) -> Self {
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Item<W = u64> {
+    worry_level: W,
+}
+impl<W> Item<W> {
+    fn new(worry_level:W) -> Self {
         Self {worry_level}
     }
 }
-impl fmt::Display for Item {
+impl<W: fmt::Display> fmt::Display for Item<W> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "{}", self.worry_level)
     }
 }
 
 #[derive(Debug, PartialEq, Eq)]
-struct Throw {
+struct Throw<W = u64> {
     monkey: usize,
-    item: Item,
+    item: Item<W>,
 }
-impl Throw {
-    fn new(monkey:usize, item:Item) -> Self {
+impl<W> Throw<W> {
+    fn new(monkey:usize, item:Item<W>) -> Self {
         Self{monkey, item}
     }
 }
 
-struct Monkey {
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Monkey<W = u64> {
     id: usize,
-    items: Vec<Item>,
-    worry_level_mod: Box<dyn Fn(usize) -> usize>,
+    items: Vec<Item<W>>,
+    operation: Expr,
     throws_to: (usize, usize),
-    divisible_by: usize,
+    divisible_by: u64,
     inspection_count: usize,
 }
 
-impl Monkey {
-    fn throws(&mut self, part2:Option<usize>) -> Vec<Throw> {
+/// How worry levels calm down after each inspection
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Relief {
+    /// part 1 divides by 3
+    Divide(u64),
+    /// part 2 keeps worry levels modulo the product of every monkey's test
+    Modulo(u64),
+    None,
+}
+impl Relief {
+    fn modulus<W>(monkeys:&[Monkey<W>]) -> Result<Self, Day11Error> {
+        monkeys.iter().try_fold(1u64, |modulus, m| modulus.checked_mul(m.divisible_by))
+            .map(Relief::Modulo)
+            .ok_or(Day11Error::ModulusOverflow)
+    }
+}
+
+impl<W: Worry> Monkey<W> {
+    fn throws(&mut self, relief:Relief) -> Result<Vec<Throw<W>>, Day11Error> {
         self.inspection_count += self.items.len();
         let throws = self.throws_to;
-        let worry_modifier = &self.worry_level_mod;
+        let operation = &self.operation;
         let test = self.divisible_by;
+        let id = self.id;
+        let err = |err| Day11Error::Worry{monkey: id, err};
         self.items.drain(..).map(|mut item| {
-            // inspect item... modify worry level (part1: divide by 3, part 2 modulus)
-            item.worry_level = match part2 {
-                Some(v) => (*worry_modifier)(item.worry_level) % v,
-                None => (*worry_modifier)(item.worry_level) / 3,
-            };
-            let destination_monkey = if item.worry_level % test == 0 { throws.0 } else { throws.1 };
-            Throw::new(destination_monkey, item)
-        }).collect::<Vec<Throw>>()
-    }
-    fn catch(&mut self, throw:Throw) {
+            // inspect item... modify worry level
+            let worry_level = operation.eval(&item.worry_level).map_err(err)?;
+            item.worry_level = match relief {
+                Relief::Divide(v) => worry_level.try_div(&worry_level.constant(v)),
+                Relief::Modulo(v) => worry_level.modulo(v),
+                Relief::None => Ok(worry_level),
+            }.map_err(err)?;
+            let destination_monkey = if item.worry_level.rem_u64(test).map_err(err)? == 0 { throws.0 } else { throws.1 };
+            Ok(Throw::new(destination_monkey, item))
+        }).collect::<Result<Vec<Throw<W>>, Day11Error>>()
+    }
+    fn catch(&mut self, throw:Throw<W>) {
         assert_eq!(self.id, throw.monkey);
         self.items.push(throw.item);
     }
 }
 
-impl FromStr for Monkey {
-    type Err = Day11Error;
+impl Monkey {
+    /// The same monkey with its worry levels in another representation
+    fn with_worry<W: Worry>(self, ctx:&W::Context) -> Monkey<W> {
+        Monkey{
+            id: self.id,
+            items: self.items.into_iter().map(|item| Item::new(W::from_u64(item.worry_level, ctx))).collect(),
+            operation: self.operation,
+            throws_to: self.throws_to,
+            divisible_by: self.divisible_by,
+            inspection_count: self.inspection_count,
+        }
+    }
+}
 
-    // egads this is ugly
-    fn from_str(def:&str) -> Result<Self, Day11Error> {
-        let lines = def.split("\n").collect::<Vec<&str>>();
-        assert!(lines.len() >= 6);
-        let id = lines[0].replace("Monkey ", "").replace(":", "")
-            .parse::<usize>().or(Err(Day11Error::MonkeyParseError))?;
-        let items:Vec<Item> = lines[1].replace("  Starting items: ", "").replace(" ", "").split(",")
-            .map(|i| i.parse::<usize>().or(Err(Day11Error::MonkeyParseError)))
-            .filter_map(|i| i.ok()).map(|w| Item::new(w)).collect::<Vec<Item>>();
-
-        let w = lines[2].replace("  Operation: new = ", "");
-        let worry_modification_parts:Vec<&str> = w.split(" ").collect();
-        let divisible_by = lines[3].replace("  Test: divisible by ", "")
-            .parse::<usize>().or(Err(Day11Error::MonkeyParseError))?;
-        let true_throw = lines[4].replace("    If true: throw to monkey ", "")
-            .parse::<usize>().or(Err(Day11Error::MonkeyParseError))?;
-        let false_throw = lines[5].replace("    If false: throw to monkey ", "")
-            .parse::<usize>().or(Err(Day11Error::MonkeyParseError))?;
+static MONKEY_PREFIX:&str = "Monkey";
+static ITEMS_PREFIX:&str = "Starting items:";
+static OPERATION_PREFIX:&str = "Operation: new =";
+static TEST_PREFIX:&str = "Test: divisible by";
+static TRUE_PREFIX:&str = "If true: throw to monkey";
+static FALSE_PREFIX:&str = "If false: throw to monkey";
+
+/// The lines of one monkey's block, whitespace normalised
+struct BlockLines<'a> {
+    block: usize,
+    lines: std::slice::Iter<'a, (usize, &'a str)>,
+    /// line number past the end of the block, for missing lines
+    end: usize,
+}
+impl BlockLines<'_> {
+    fn error(&self, line:usize, expected:&'static str, found:&str) -> Day11Error {
+        Day11Error::Parse{block: self.block, line, expected, found: found.to_string()}
+    }
+
+    /// The line number and text after the prefix on the next line
+    fn next(&mut self, prefix:&str, expected:&'static str) -> Result<(usize, String), Day11Error> {
+        let (line, text) = self.lines.next().map_or((self.end, String::new()), |(line, text)| {
+            (*line, text.split_whitespace().collect::<Vec<&str>>().join(" "))
+        });
+        match text.strip_prefix(prefix) {
+            Some(rest) => Ok((line, rest.trim().to_string())),
+            None => Err(self.error(line, expected, &text)),
+        }
+    }
+
+    fn next_number<T: FromStr>(&mut self, prefix:&str, expected:&'static str) -> Result<(usize, T), Day11Error> {
+        let (line, rest) = self.next(prefix, expected)?;
+        rest.parse().map(|v| (line, v)).map_err(|_| self.error(line, expected, &format!("{} {}", prefix, rest)))
+    }
+}
+
+impl Monkey {
+    /// Parse one monkey from its non-blank lines, each with its line number
+    fn parse_block(block:usize, lines:&[(usize, &str)]) -> Result<Self, Day11Error> {
+        let end = lines.last().map_or(1, |(line, _)| line + 1);
+        let mut lines = BlockLines{block, lines: lines.iter(), end};
+
+        let (line, header) = lines.next(MONKEY_PREFIX, "Monkey <id>:")?;
+        let id = header.strip_suffix(':').and_then(|id| id.trim().parse().ok())
+            .ok_or_else(|| lines.error(line, "Monkey <id>:", &format!("{} {}", MONKEY_PREFIX, header)))?;
+        let (line, items) = lines.next(ITEMS_PREFIX, "Starting items: <worry>, ...")?;
+        let items = items.split(',').map(str::trim).filter(|i| !i.is_empty())
+            .map(|i| i.parse::<u64>().map(Item::new)).collect::<Result<Vec<Item>, _>>()
+            .map_err(|_| lines.error(line, "Starting items: <worry>, ...", &format!("{} {}", ITEMS_PREFIX, items)))?;
+        let (line, operation) = lines.next(OPERATION_PREFIX, "Operation: new = <expression>")?;
+        let operation = operation.parse::<Expr>()
+            .map_err(|_| lines.error(line, "Operation: new = <expression>", &format!("{} {}", OPERATION_PREFIX, operation)))?;
+        let (line, divisible_by) = lines.next_number::<u64>(TEST_PREFIX, "Test: divisible by <n>")?;
+        if divisible_by == 0 {
+            return Err(lines.error(line, "Test: divisible by <n>", &format!("{} 0", TEST_PREFIX)));
+        }
+        let (_, true_throw) = lines.next_number(TRUE_PREFIX, "If true: throw to monkey <id>")?;
+        let (_, false_throw) = lines.next_number(FALSE_PREFIX, "If false: throw to monkey <id>")?;
+        if let Some((line, text)) = lines.lines.next() {
+            return Err(lines.error(*line, "a blank line between monkeys", text.trim()));
+        }
 
         Ok(Monkey{
             id,
             items,
-            worry_level_mod: match worry_modification_parts[..] {
-                ["old", "+", "old"] => {
-                    Box::new(|worry_level| {
-                        worry_level.wrapping_add(worry_level)
-                    })
-                },
-                ["old", "+", v] => {
-                    let value = v.parse::<usize>().or(Err(Day11Error::MonkeyParseError))?;
-                    Box::new(move |worry_level| {
-                        worry_level.wrapping_add(value)
-                    })
-                },
-                ["old", "*", "old"] => {
-                    Box::new(|worry_level| {
-                        worry_level.wrapping_mul(worry_level)
-                    })
-                },
-                ["old", "*", v] => {
-                    let value = v.parse::<usize>().or(Err(Day11Error::MonkeyParseError))?;
-                    Box::new(move |worry_level| {
-                        worry_level.wrapping_mul(value)
-                    })
-                },
-                _ => Box::new(|worry_level| worry_level),
-            },
+            operation,
             throws_to: (true_throw, false_throw),
             divisible_by,
             inspection_count: 0,
@@ -153,99 +688,489 @@ impl FromStr for Monkey {
     }
 }
 
-impl fmt::Display for Monkey {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+impl FromStr for Monkey {
+    type Err = Day11Error;
+
+    fn from_str(def:&str) -> Result<Self, Day11Error> {
+        let lines:Vec<(usize, &str)> = def.lines().enumerate().map(|(idx, l)| (idx + 1, l)).filter(|(_, l)| !l.trim().is_empty()).collect();
+        Monkey::parse_block(1, &lines)
+    }
+}
+
+impl<W: fmt::Display> Monkey<W> {
+    /// One line description of the items held
+    fn summary(&self) -> String {
         let items = if self.items.is_empty() {
             "<no items>".to_string()
         } else {
             self.items.iter().map(|i| i.to_string()).collect::<Vec<String>>().join(", ")
         };
-        write!(f, "Monkey {}: {}", self.id, items)
+        format!("Monkey {}: {}", self.id, items)
     }
 }
 
-fn parse_monkeys_from_file(file_path:&str) -> Vec<Monkey> {
-    match read_to_string(&file_path) {
-        Ok(content) => {
-            let entries = content.lines().collect::<Vec<&str>>();
-            entries
-                .chunks(7)
-                .map(|v| v.join("\n").parse::<Monkey>())
-                .filter_map(|i| i.ok() )
-                .collect::<Vec<Monkey>>()
-        },
-        Err(_) => Vec::new(),
+/// The puzzle's notation, parses back to the same monkey
+impl<W: fmt::Display> fmt::Display for Monkey<W> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Monkey {}:", self.id)?;
+        writeln!(f, "  Starting items: {}", self.items.iter().map(|i| i.to_string()).collect::<Vec<String>>().join(", "))?;
+        writeln!(f, "  Operation: new = {}", self.operation)?;
+        writeln!(f, "  Test: divisible by {}", self.divisible_by)?;
+        writeln!(f, "    If true: throw to monkey {}", self.throws_to.0)?;
+        write!(f, "    If false: throw to monkey {}", self.throws_to.1)
     }
 }
 
-fn main()
-{
-    let args:Vec<String> = env::args().collect();
-    if let None = args.get(1) {
-        println!("Usage: {} <path>", args[0]);
-        std::process::exit(1);
+/// Monkeys separated by blank lines, numbered from 0 and only throwing to each other
+fn parse_monkeys(content:&str) -> Result<Vec<Monkey>, Day11Error> {
+    let mut blocks:Vec<Vec<(usize, &str)>> = Vec::new();
+    let mut blank = true;
+    for (idx, line) in content.lines().enumerate() {
+        if line.trim().is_empty() {
+            blank = true;
+            continue;
+        }
+        if blank {
+            blocks.push(Vec::new());
+            blank = false;
+        }
+        if let Some(block) = blocks.last_mut() {
+            block.push((idx + 1, line));
+        }
     }
+    if blocks.is_empty() {
+        return Err(Day11Error::NoMonkeys);
+    }
+
+    let monkeys = blocks.iter().enumerate().map(|(idx, lines)| Monkey::parse_block(idx + 1, lines)).collect::<Result<Vec<Monkey>, Day11Error>>()?;
+    for (idx, (monkey, lines)) in monkeys.iter().zip(&blocks).enumerate() {
+        if monkey.id != idx {
+            return Err(Day11Error::NonContiguousId{block: idx + 1, line: lines[0].0, expected: idx, found: monkey.id});
+        }
+        // the throw lines are the last two of a block
+        for (&target, &(line, _)) in [monkey.throws_to.0, monkey.throws_to.1].iter().zip(&lines[4..]) {
+            if target >= monkeys.len() {
+                return Err(Day11Error::UnknownMonkey{block: idx + 1, line, monkey: target});
+            }
+        }
+    }
+    Ok(monkeys)
+}
+
+fn parse_monkeys_from_file(file_path:&str) -> Result<Vec<Monkey>, Day11Error> {
+    parse_monkeys(&read_to_string(file_path).map_err(|_| Day11Error::Io)?)
+}
 
-    let mut monkeys = parse_monkeys_from_file(&args[1]);
-    assert!(monkeys.len() > 0);
+/// Rounds after which the monkeys are back where they were
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Cycle {
+    /// first round of the repeating part
+    start: usize,
+    length: usize,
+}
 
-    // part2 we don't divide by 3, instead of have to find the least
-    // common multiple of the divisible_by values
-    let part2:usize = monkeys.iter().map(|m| m.divisible_by).product();
+/// The monkeys' game and how long to watch it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct KeepAway {
+    rounds: usize,
+    relief: Relief,
+    /// how many of the busiest monkeys make up the monkey business
+    top: usize,
+}
+impl Default for KeepAway {
+    /// part 1
+    fn default() -> Self {
+        Self{rounds: 20, relief: Relief::Divide(3), top: 2}
+    }
+}
+impl KeepAway {
+    fn part2<W>(monkeys:&[Monkey<W>]) -> Result<Self, Day11Error> {
+        Ok(Self{rounds: 10000, relief: Relief::modulus(monkeys)?, top: 2})
+    }
 
-    for round in 0..10000 { // part 1 is 20 rounds, part 2 is 10000
-        // first round
+    fn round<W: Worry>(&self, monkeys:&mut [Monkey<W>]) -> Result<(), Day11Error> {
         for idx in 0..monkeys.len() {
-            let m = &mut monkeys[idx];
-            let throws:Vec<Throw> = m.throws(Some(part2));
+            let throws:Vec<Throw<W>> = monkeys[idx].throws(self.relief)?;
             for throw in throws {
                 let destination_monkey = &mut monkeys[throw.monkey];
                 destination_monkey.catch(throw);
             }
         }
+        Ok(())
+    }
+
+    /// Play every round, handing the monkeys to on_round after each, numbered from 1
+    fn play<W: Worry, F: FnMut(usize, &[Monkey<W>])>(&self, monkeys:&mut [Monkey<W>], mut on_round:F) -> Result<u128, Day11Error> {
+        for round in 0..self.rounds {
+            self.round(monkeys)?;
+            on_round(round + 1, monkeys);
+        }
+        self.monkey_business(monkeys)
+    }
+
+    /// Play every round, but once the monkeys return to a state seen in the first `limit`
+    /// rounds skip the whole cycles left and only play the remainder. The state is where
+    /// every item is and its worry level, order within a monkey does not change the counts.
+    /// Only a hash of each state is kept, a repeated hash is confirmed by replaying the game
+    /// up to the earlier round. on_round sees each round actually played, numbered as in the
+    /// full game. Fails with `NoCycle` if the game outlasts the search, None if it ended
+    /// before a state repeated.
+    fn play_with_cycles<W, F>(&self, monkeys:&mut [Monkey<W>], limit:usize, mut on_round:F) -> Result<(u128, Option<Cycle>), Day11Error>
+    where
+        W: Worry + Hash + Ord,
+        F: FnMut(usize, &[Monkey<W>]),
+    {
+        let state = |monkeys:&[Monkey<W>]| -> Vec<Vec<W>> {
+            monkeys.iter().map(|m| {
+                let mut items:Vec<W> = m.items.iter().map(|i| i.worry_level.clone()).collect();
+                items.sort();
+                items
+            }).collect()
+        };
+        let hash = |state:&Vec<Vec<W>>| -> u64 {
+            let mut hasher = DefaultHasher::new();
+            state.hash(&mut hasher);
+            hasher.finish()
+        };
+
+        let initial = monkeys.to_vec();
+        let mut seen:HashMap<u64, usize> = HashMap::new();
+        seen.insert(hash(&state(monkeys)), 0);
+        // the game as it was when the cycle started
+        let mut start_monkeys = None;
+        let mut cycle = None;
+        let mut round = 0;
+        while round < self.rounds && cycle.is_none() {
+            if round == limit {
+                return Err(Day11Error::NoCycle{limit});
+            }
+            self.round(monkeys)?;
+            round += 1;
+            on_round(round, monkeys);
+            let current = state(monkeys);
+            let key = hash(&current);
+            if let Some(&start) = seen.get(&key) {
+                let mut replay = initial.clone();
+                for _ in 0..start {
+                    self.round(&mut replay)?;
+                }
+                if state(&replay) == current {
+                    cycle = Some(Cycle{start, length: round - start});
+                    start_monkeys = Some(replay);
+                    continue;
+                }
+            }
+            // a colliding state replaces the earlier one, the game keeps searching
+            seen.insert(key, round);
+        }
+        if let (Some(Cycle{length, ..}), Some(start_monkeys)) = (cycle, start_monkeys) {
+            let remaining = self.rounds - round;
+            // every skipped cycle adds the inspections of one pass through it
+            for (monkey, at_start) in monkeys.iter_mut().zip(start_monkeys) {
+                monkey.inspection_count += remaining / length * (monkey.inspection_count - at_start.inspection_count);
+            }
+            for round in self.rounds - remaining % length..self.rounds {
+                self.round(monkeys)?;
+                on_round(round + 1, monkeys);
+            }
+        }
+        Ok((self.monkey_business(monkeys)?, cycle))
+    }
 
-        println!("Round {}", round + 1);
+    /// product of the top inspection counts
+    fn monkey_business<W>(&self, monkeys:&[Monkey<W>]) -> Result<u128, Day11Error> {
+        let mut inspections:Vec<usize> = monkeys.iter().map(|m| -> usize {m.inspection_count}).collect::<Vec<usize>>();
+        inspections.sort_by(|e1, e2| e2.cmp(e1));
+        inspections.iter().take(self.top).try_fold(1u128, |product, &count| product.checked_mul(count as u128))
+            .ok_or(Day11Error::MonkeyBusinessOverflow)
+    }
+}
+
+/// Play the game, looking for cycles in the first cycle_limit rounds when given
+fn day11<W: Worry + Hash + Ord>(monkeys:&[Monkey], ctx:&W::Context, game:&KeepAway, verbose:bool, cycle_limit:Option<usize>) -> Result<(u128, Option<Cycle>), Day11Error> {
+    let mut monkeys:Vec<Monkey<W>> = monkeys.iter().cloned().map(|m| m.with_worry(ctx)).collect();
+    let dump = |round:usize, monkeys:&[Monkey<W>]| {
+        if verbose {
+            println!("Round {}", round);
+            for monkey in monkeys {
+                println!("\t{}", monkey.summary());
+            }
+        }
+    };
+    let (monkey_business, cycle) = match cycle_limit {
+        Some(limit) => game.play_with_cycles(&mut monkeys, limit, dump)?,
+        None => (game.play(&mut monkeys, dump)?, None),
+    };
+    if verbose {
         for monkey in &monkeys {
-            println!("\t{}", monkey);
+            println!("Monkey {} inspected items {} times.", monkey.id, monkey.inspection_count);
         }
     }
-    for monkey in &monkeys {
-        println!("Monkey {} inspected items {} times.", monkey.id, monkey.inspection_count);
+    Ok((monkey_business, cycle))
+}
+
+fn main()
+{
+    let args:Vec<String> = env::args().collect();
+    let usage = || {
+        println!("Usage: {} <path> [--rounds <n>] [--relief divide[:<n>]|modulus|none] [--top <k>] [--worry u64|u128|big|rns] [--verbose]
+    [--cycles [<search rounds>]]", args[0]);
+        std::process::exit(1);
+    };
+    if args.get(1).is_none() {
+        usage();
+    }
+
+    let monkeys = match parse_monkeys_from_file(&args[1]) {
+        Ok(monkeys) => monkeys,
+        Err(e) => {
+            println!("{:?}", e);
+            std::process::exit(1);
+        },
+    };
+
+    let option = |name:&str| args.iter().position(|a| a == name).and_then(|pos| args.get(pos + 1)).map(|v| v.as_str());
+    let verbose = args.iter().skip(2).any(|a| a == "--verbose");
+    let cycle_limit = match args.iter().position(|a| a == "--cycles") {
+        None => None,
+        Some(pos) => match args.get(pos + 1).filter(|l| !l.starts_with("--")).map(|l| l.parse()) {
+            None => Some(100_000),
+            Some(Ok(limit)) => Some(limit),
+            Some(Err(_)) => return usage(),
+        },
+    };
+    let relief = match option("--relief") {
+        None => None,
+        Some("modulus") => match Relief::modulus(&monkeys) {
+            Ok(relief) => Some(relief),
+            Err(e) => {
+                println!("{:?}", e);
+                std::process::exit(1);
+            },
+        },
+        Some("none") => Some(Relief::None),
+        Some("divide") => Some(Relief::Divide(3)),
+        Some(divide) => match divide.strip_prefix("divide:").and_then(|n| n.parse().ok()) {
+            Some(n) if n > 0 => Some(Relief::Divide(n)),
+            _ => return usage(),
+        },
+    };
+    let rounds = match option("--rounds").map(|r| r.parse()) {
+        None => None,
+        Some(Ok(rounds)) => Some(rounds),
+        Some(Err(_)) => return usage(),
+    };
+    let top = match option("--top").map(|t| t.parse()) {
+        None => 2,
+        Some(Ok(top)) => top,
+        Some(Err(_)) => return usage(),
+    };
+    // without a game set up play both parts of the puzzle
+    let games = if relief.is_none() && rounds.is_none() {
+        match KeepAway::part2(&monkeys) {
+            Ok(part2) => vec![KeepAway{top, ..KeepAway::default()}, KeepAway{top, ..part2}],
+            Err(e) => {
+                println!("{:?}", e);
+                std::process::exit(1);
+            },
+        }
+    } else {
+        let game = KeepAway::default();
+        vec![KeepAway{rounds: rounds.unwrap_or(game.rounds), relief: relief.unwrap_or(game.relief), top}]
+    };
+
+    for game in &games {
+        let monkey_business = match option("--worry") {
+            None | Some("u64") => day11::<u64>(&monkeys, &(), game, verbose, cycle_limit),
+            Some("u128") => day11::<u128>(&monkeys, &(), game, verbose, cycle_limit),
+            Some("big") => day11::<BigUint>(&monkeys, &(), game, verbose, cycle_limit),
+            Some("rns") => {
+                let moduli = Rc::new(monkeys.iter().map(|m| m.divisible_by).collect());
+                day11::<Residues>(&monkeys, &moduli, game, verbose, cycle_limit)
+            },
+            Some(_) => return usage(),
+        };
+        match monkey_business {
+            Ok((monkey_business, cycle)) => {
+                println!("{} rounds with {:?}: monkey business {}", game.rounds, game.relief, monkey_business);
+                if let Some(cycle) = cycle {
+                    println!("\tstate repeats every {} rounds from round {}", cycle.length, cycle.start);
+                }
+            },
+            Err(e) => println!("{} rounds with {:?}: {:?}", game.rounds, game.relief, e),
+        }
     }
-    let mut inspections:Vec<usize> = monkeys.iter().map(|m| -> usize {m.inspection_count}).collect::<Vec<usize>>();
-    inspections.sort_by(|e1, e2| e2.cmp(&e1));
-    let monkey_business:usize = inspections.iter().take(2).product();
-    println!("{}", monkey_business);
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{Monkey, Item, Throw};
+    use super::{parse_monkeys, parse_monkeys_from_file, BigUint, Cycle, Day11Error, Expr, KeepAway, Monkey, Item, Relief, Residues, Throw, Worry, WorryErr};
+    use std::rc::Rc;
 
     #[test]
     fn test_monkey() {
-        let mut m = Monkey{
+        let mut m:Monkey = Monkey{
             id: 0,
             items: vec![Item::new(79), Item::new(98)],
-            worry_level_mod: Box::new(|worry_level| worry_level * 19),
+            operation: "old * 19".parse().unwrap(),
             throws_to: (2, 3),
             divisible_by: 23,
             inspection_count: 0,
         };
         assert_eq!(m.id, 0);
         assert_eq!(m.items, vec![Item::new(79), Item::new(98)]);
-        assert_eq!((m.worry_level_mod)(3), 57);
+        assert_eq!(m.operation.eval(&3u64), Ok(57));
         // part1 assert_eq!(m.throws(), vec![Throw::new(3, Item::new(500)), Throw::new(3, Item::new(620))]);
-        assert_eq!(m.throws(Some(m.divisible_by)), vec![Throw::new(3, Item::new(6)), Throw::new(3, Item::new(22))]);
+        assert_eq!(m.throws(Relief::Modulo(m.divisible_by)), Ok(vec![Throw::new(3, Item::new(6)), Throw::new(3, Item::new(22))]));
 
         assert!(m.items.is_empty());
         assert_eq!(m.inspection_count, 2);
 
 
         let m = "Monkey 0:\n  Starting items: 79, 98\n  Operation: new = old * 19\n  Test: divisible by 23\n    If true: throw to monkey 2\n    If false: throw to monkey 3\n".parse::<Monkey>().expect("failed to parse test case");
-        assert_eq!((m.worry_level_mod)(1), 19); // old * 19
+        assert_eq!(m.operation.eval(&1u64), Ok(19)); // old * 19
 
         let m = "Monkey 2:\n  Starting items: 79, 60, 97\n  Operation: new = old * old\n  Test: divisible by 13\n    If true: throw to monkey 1\n    If false: throw to monkey 3\n".parse::<Monkey>().expect("failed to parse test case");
-        assert_eq!((m.worry_level_mod)(4), 16); // old * old
+        assert_eq!(m.operation.eval(&4u64), Ok(16)); // old * old
+        assert_eq!(m.to_string().parse::<Monkey>(), Ok(m));
+    }
+
+    #[test]
+    fn test_expr() {
+        let e = "(old + 3) * old - 10 / (old - 2) - 1".parse::<Expr>().unwrap();
+        assert_eq!(e.eval(&4u64), Ok(22));
+        assert_eq!(e.eval(&2u64), Err(WorryErr::DivideByZero));
+        assert_eq!(e.eval(&1u64), Err(WorryErr::Underflow));
+        assert_eq!(e.to_string(), "(old + 3) * old - 10 / (old - 2) - 1");
+        for text in &["old", "7", "old - (old - 1)", "old / (old * 2)", "(old + 1) * (old + 2)"] {
+            let e = text.parse::<Expr>().unwrap();
+            assert_eq!(&e.to_string(), text);
+            assert_eq!(e.to_string().parse::<Expr>(), Ok(e));
+        }
+        assert_eq!("old * 2 * 3".parse::<Expr>().unwrap().eval(&1u64), Ok(6));
+        assert_eq!("old ^ 2".parse::<Expr>(), Err(Day11Error::InvalidOperation("old ^ 2".to_string())));
+        assert_eq!("old +".parse::<Expr>(), Err(Day11Error::InvalidOperation("old +".to_string())));
+        assert_eq!("new * 2".parse::<Expr>(), Err(Day11Error::InvalidOperation("new * 2".to_string())));
+        assert_eq!("(old".parse::<Expr>(), Err(Day11Error::InvalidOperation("(old".to_string())));
+        assert_eq!("old / 0".parse::<Expr>(), Err(Day11Error::InvalidOperation("old / 0".to_string())));
+    }
+
+    #[test]
+    fn test_worry() {
+        let square = "old * old".parse::<Expr>().unwrap();
+        assert_eq!(square.eval(&(1u64 << 32)), Err(WorryErr::Overflow));
+        assert_eq!(square.eval(&(1u128 << 32)), Ok(1u128 << 64));
+
+        // 2^64 + 1 squared and back again
+        let big = BigUint::from_u64(u64::MAX, &()).try_add(&BigUint::new(2)).unwrap();
+        let squared = square.eval(&big).unwrap();
+        assert_eq!(squared.to_string(), "340282366920938463500268095579187314689");
+        assert_eq!(squared.try_div(&big), Ok(big.clone()));
+        assert_eq!(squared.try_sub(&squared).map(|z| z.to_string()), Ok("0".to_string()));
+        assert_eq!(big.try_sub(&squared), Err(WorryErr::Underflow));
+        assert_eq!(squared.rem_u64(1_000_000_007), Ok((((1u128 << 64) + 1) % 1_000_000_007).pow(2) as u64 % 1_000_000_007));
+        assert_eq!(big.try_div(&BigUint::new(3)).map(|q| q.to_string()), Ok("6148914691236517205".to_string()));
+
+        let moduli = Rc::new(vec![23, 19, 13, 17]);
+        let r = Residues::from_u64(79, &moduli);
+        let r = "old * old + 6".parse::<Expr>().unwrap().eval(&r).unwrap();
+        assert_eq!(r.values, vec![(79 * 79 + 6) % 23, (79 * 79 + 6) % 19, (79 * 79 + 6) % 13, (79 * 79 + 6) % 17]);
+        assert_eq!(r.rem_u64(13), Ok((79 * 79 + 6) % 13));
+        assert_eq!(r.rem_u64(7), Err(WorryErr::Unsupported));
+        assert_eq!(r.try_div(&r), Err(WorryErr::Unsupported));
+        assert_eq!(r.modulo(23 * 19 * 13 * 17), Ok(r.clone()));
+
+        let mut m = "Monkey 0:\n  Starting items: 79, 98\n  Operation: new = old * 19\n  Test: divisible by 23\n    If true: throw to monkey 2\n    If false: throw to monkey 3\n".parse::<Monkey>().unwrap().with_worry::<Residues>(&moduli);
+        let throws = m.throws(Relief::Divide(3));
+        assert_eq!(throws, Err(Day11Error::Worry{monkey: 0, err: WorryErr::Unsupported}));
+    }
+
+    #[test]
+    fn test_keep_away() {
+        let monkeys = parse_monkeys_from_file("test").unwrap();
+        assert_eq!(monkeys.len(), 4);
+
+        let mut part1 = monkeys.clone();
+        let mut snapshots = Vec::new();
+        let game = KeepAway::default();
+        assert_eq!(game.play(&mut part1, |round, monkeys| snapshots.push((round, monkeys[0].summary()))), Ok(10605));
+        assert_eq!(snapshots.len(), 20);
+        assert_eq!(snapshots[0], (1, "Monkey 0: 20, 23, 27, 26".to_string()));
+        assert_eq!(snapshots[19], (20, "Monkey 0: 10, 12, 14, 26, 34".to_string()));
+        assert_eq!(part1.iter().map(|m| m.inspection_count).collect::<Vec<usize>>(), vec![101, 95, 7, 105]);
+        assert_eq!(KeepAway{top: 3, ..game}.monkey_business(&part1), Ok(101 * 95 * 105));
+
+        let mut part2 = monkeys.clone();
+        assert_eq!(KeepAway::part2(&monkeys).unwrap().play(&mut part2, |_, _| {}), Ok(2713310158));
+        assert_eq!(Relief::modulus(&monkeys), Ok(Relief::Modulo(23 * 19 * 13 * 17)));
+
+        // the modulus has to fit in a u64
+        let mut huge = monkeys.clone();
+        huge[0].divisible_by = 1 << 40;
+        huge[1].divisible_by = 1 << 30;
+        assert_eq!(Relief::modulus(&huge), Err(Day11Error::ModulusOverflow));
+        assert_eq!(KeepAway::part2(&huge), Err(Day11Error::ModulusOverflow));
+
+        // without relief the worry levels soon overflow
+        let game = KeepAway{rounds: 20, relief: Relief::None, top: 2};
+        assert_eq!(game.play(&mut monkeys.clone(), |_, _| {}), Err(Day11Error::Worry{monkey: 0, err: WorryErr::Overflow}));
+    }
+
+    #[test]
+    fn test_cycles() {
+        // one item passed back and forth, adding 1 at each monkey: 1 -> 3 -> 5 -> 1 modulo 6
+        let monkeys = parse_monkeys(concat!(
+            "Monkey 0:\n  Starting items: 1\n  Operation: new = old + 1\n  Test: divisible by 2\n    If true: throw to monkey 1\n    If false: throw to monkey 1\n\n",
+            "Monkey 1:\n  Starting items:\n  Operation: new = old + 1\n  Test: divisible by 3\n    If true: throw to monkey 0\n    If false: throw to monkey 0\n",
+        )).unwrap();
+        let game = KeepAway{rounds: 1000, ..KeepAway::part2(&monkeys).unwrap()};
+        let mut played = monkeys.clone();
+        let monkey_business = game.play(&mut played, |_, _| {}).unwrap();
+        assert_eq!(monkey_business, 1000 * 1000);
+
+        let mut skipped = monkeys.clone();
+        assert_eq!(game.play_with_cycles(&mut skipped, 10, |_, _| {}), Ok((monkey_business, Some(Cycle{start: 0, length: 3}))));
+        assert_eq!(skipped, played);
+
+        // rounds are reported as played, the skipped ones are not
+        let game = KeepAway{rounds: 10, ..game};
+        let mut rounds = Vec::new();
+        assert_eq!(game.play_with_cycles(&mut monkeys.clone(), 10, |round, _| rounds.push(round)), Ok((100, Some(Cycle{start: 0, length: 3}))));
+        assert_eq!(rounds, vec![1, 2, 3, 10]);
+
+        // the search gives up rather than playing every round
+        assert_eq!(game.play_with_cycles(&mut monkeys.clone(), 2, |_, _| {}), Err(Day11Error::NoCycle{limit: 2}));
+        // a game over before anything repeats needs no cycle
+        let game = KeepAway{rounds: 2, ..game};
+        assert_eq!(game.play_with_cycles(&mut monkeys.clone(), 2, |_, _| {}), Ok((4, None)));
+
+        let game = KeepAway{rounds: 1_000_000_000_000, ..game};
+        assert_eq!(game.play_with_cycles(&mut monkeys.clone(), 10, |_, _| {}), Ok((1_000_000_000_000 * 1_000_000_000_000, Some(Cycle{start: 0, length: 3}))));
+    }
+
+    #[test]
+    fn test_parse() {
+        let messy = "\r\n\r\nMonkey 0:\r\n  Starting items:   79,98 \r\n\tOperation: new  =  old*19\r\n  Test: divisible by 23\r\n If true: throw to monkey 1\r\n    If false: throw to monkey 1\r\n\r\n\r\n   \r\nMonkey 1:\n  Starting items:\n  Operation: new = old + 3\n  Test: divisible by 17\n    If true: throw to monkey 0\n    If false: throw to monkey 0";
+        let monkeys = parse_monkeys(messy).unwrap();
+        assert_eq!(monkeys.len(), 2);
+        assert_eq!(monkeys[0].items, vec![Item::new(79), Item::new(98)]);
+        assert_eq!(monkeys[0].to_string().lines().nth(2), Some("  Operation: new = old * 19"));
+        assert!(monkeys[1].items.is_empty());
+
+        let block = |id:usize, op:&str, to:usize| format!("Monkey {}:\n  Starting items: 1\n  Operation: new = {}\n  Test: divisible by 2\n    If true: throw to monkey {}\n    If false: throw to monkey 0\n", id, op, to);
+        assert_eq!(parse_monkeys(&format!("{}\n{}", block(0, "old", 1), block(2, "old", 0))),
+            Err(Day11Error::NonContiguousId{block: 2, line: 8, expected: 1, found: 2}));
+        assert_eq!(parse_monkeys(&format!("{}\n{}", block(0, "old", 1), block(1, "old", 5))),
+            Err(Day11Error::UnknownMonkey{block: 2, line: 12, monkey: 5}));
+        assert_eq!(parse_monkeys(&format!("{}\n{}", block(0, "old", 1), block(1, "old ** 2", 0))),
+            Err(Day11Error::Parse{block: 2, line: 10, expected: "Operation: new = <expression>", found: "Operation: new = old ** 2".to_string()}));
+        // a missing blank line runs two monkeys together
+        assert_eq!(parse_monkeys(&format!("{}{}", block(0, "old", 0), block(1, "old", 0))),
+            Err(Day11Error::Parse{block: 1, line: 7, expected: "a blank line between monkeys", found: "Monkey 1:".to_string()}));
+        assert_eq!(parse_monkeys("Monkey 0:\n  Starting items: 1, x\n"),
+            Err(Day11Error::Parse{block: 1, line: 2, expected: "Starting items: <worry>, ...", found: "Starting items: 1, x".to_string()}));
+        assert_eq!(parse_monkeys("Monkey 0:\n  Starting items: 1\n"),
+            Err(Day11Error::Parse{block: 1, line: 3, expected: "Operation: new = <expression>", found: String::new()}));
+        assert_eq!(parse_monkeys(" \n\n"), Err(Day11Error::NoMonkeys));
     }
 }
@@ -1,9 +1,13 @@
 /* Supply Stacks */
 use std::env;
+use std::fmt;
 use std::fs::File;
 use std::io::prelude::*;
 use std::io::BufReader;
+use std::path::PathBuf;
 use std::str::FromStr;
+use std::thread;
+use std::time::Duration;
 
 #[derive(Debug, PartialEq, Eq)]
 enum ElfCrateErr {
@@ -13,6 +17,18 @@ enum ElfCrateErr {
     InvalidSourceIndex,
     InvalidDestinationIndex,
     EmptyElfCrate,
+    Io,
+    ZeroIndex,
+    SameSourceAndDestination,
+    NotEnoughCrates,
+    InvalidOps(Vec<ElfCrateOpErr>),
+}
+
+/// An operation that failed to parse or validate, along with its line number in the input
+#[derive(Debug, PartialEq, Eq)]
+struct ElfCrateOpErr {
+    line: usize,
+    err: ElfCrateErr,
 }
 
 /// Crate operation
@@ -40,42 +56,77 @@ impl FromStr for ElfCrateOperation {
         Ok(Self{count, source_index, destination_index})
     }
 }
+impl ElfCrateOperation {
+    /// check the operation against the number of stacks and the height of a stack (0 based index)
+    fn validate<F: Fn(usize) -> usize>(&self, num_of_crate_stacks:usize, stack_height:F) -> Result<(), ElfCrateErr> {
+        if self.source_index == 0 || self.destination_index == 0 {
+            return Err(ElfCrateErr::ZeroIndex);
+        }
+        if num_of_crate_stacks < self.source_index {
+            return Err(ElfCrateErr::InvalidSourceIndex);
+        }
+        if num_of_crate_stacks < self.destination_index {
+            return Err(ElfCrateErr::InvalidDestinationIndex);
+        }
+        if self.source_index == self.destination_index {
+            return Err(ElfCrateErr::SameSourceAndDestination);
+        }
+        if stack_height(self.source_index - 1) < self.count {
+            return Err(ElfCrateErr::NotEnoughCrates);
+        }
+        Ok(())
+    }
+}
+impl fmt::Display for ElfCrateOperation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "move {} from {} to {}", self.count, self.source_index, self.destination_index)
+    }
+}
 
 #[derive(Debug, PartialEq, Eq)]
 /// Elf crate stack
+///
+/// Each stack is stored bottom-first so the top crate is the last element, moves
+/// only touch the tail of the source and destination stacks.
 struct ElfCrateStack {
     crate_stacks: Vec<Vec<char>>,
 }
 impl ElfCrateStack {
     /// perform the operation on the elf crate stack
-    fn perform_op(&mut self, op: ElfCrateOperation) -> Result<(), ElfCrateErr> {
-        let num_of_crate_stacks = self.crate_stacks.len();
-        if num_of_crate_stacks < op.source_index {
-            return Err(ElfCrateErr::InvalidSourceIndex);
-        }
-        if num_of_crate_stacks < op.destination_index {
-            return Err(ElfCrateErr::InvalidDestinationIndex);
-        }
+    fn perform_op(&mut self, op: &ElfCrateOperation) -> Result<(), ElfCrateErr> {
+        op.validate(self.crate_stacks.len(), |idx| self.crate_stacks[idx].len())?;
         // Part 1
         //for _ in 0..op.count {
-            // let v = self.crate_stacks[op.source_index - 1].remove(0);
-            // self.crate_stacks[op.destination_index - 1].insert(0, v);
+            // let v = self.crate_stacks[op.source_index - 1].pop().unwrap();
+            // self.crate_stacks[op.destination_index - 1].push(v);
         //}
         // Part 2
-        let to_move:Vec<char> = self.crate_stacks[op.source_index - 1].drain(0..op.count).collect();
-        for v in to_move.into_iter().rev() {
-            self.crate_stacks[op.destination_index - 1].insert(0, v);
-        }
+        let source = &mut self.crate_stacks[op.source_index - 1];
+        let to_move:Vec<char> = source.split_off(source.len() - op.count);
+        self.crate_stacks[op.destination_index - 1].extend(to_move);
         Ok(())
     }
 
+    /// Validate the numbered operations in order without moving any crates, only the
+    /// stack heights are tracked. Every bad operation is reported and then skipped.
+    fn validate_ops(&self, ops:&[(usize, ElfCrateOperation)]) -> Vec<ElfCrateOpErr> {
+        let mut heights:Vec<usize> = self.crate_stacks.iter().map(|c| c.len()).collect();
+        let mut errors = Vec::new();
+        for (line, op) in ops {
+            match op.validate(heights.len(), |idx| heights[idx]) {
+                Ok(()) => {
+                    heights[op.source_index - 1] -= op.count;
+                    heights[op.destination_index - 1] += op.count;
+                },
+                Err(err) => errors.push(ElfCrateOpErr{line: *line, err}),
+            }
+        }
+        errors
+    }
+
     /// Return the top of all of the elf crate stacks as a string combination
     fn top_of_the_stacks(&self) -> Result<String, ElfCrateErr> {
-        if self.crate_stacks.iter().any(|c| c.len() == 0) {
-            return Err(ElfCrateErr::EmptyElfCrate);
-        }
-        let t:String = self.crate_stacks.iter().map(|c| c[0]).collect();
-        Ok(t)
+        self.crate_stacks.iter().map(|c| c.last().copied().ok_or(ElfCrateErr::EmptyElfCrate)).collect()
     }
 
     /// Parse the create header from the buffer, returning Vec<Vec<char>> of the crate labels
@@ -90,9 +141,11 @@ impl ElfCrateStack {
     /// move 2 from 2 to 1
     /// move 1 from 1 to 2
     ///
-    /// This stops as soon as we get an empty line. The trailing number line is ignored.
+    /// This stops as soon as we get an empty line. The trailing number line only sets the
+    /// column width, wider than 3 once a label has more than 3 digits.
+    /// The drawing lists crates top-first, so each stack is reversed once parsed.
     fn parse<R: Read>(f: &mut BufReader<R>) -> Result<Self, ElfCrateErr> {
-        let mut elf_crate_stacks:Vec<Vec<char>> = Vec::new();
+        let mut header:Vec<String> = Vec::new();
         for line in f.lines() {
             let entry = line.map_err(|_| ElfCrateErr::Malformed)?;
 
@@ -100,9 +153,18 @@ impl ElfCrateStack {
             if entry.is_empty() {
                 break;
             }
+            header.push(entry);
+        }
+        let width = match header.last() {
+            Some(footer) if footer.chars().all(|c| c.is_ascii_digit() || c == ' ') =>
+                footer.split_ascii_whitespace().map(|label| label.len()).max().unwrap_or(0).max(3),
+            _ => 3,
+        };
 
+        let mut elf_crate_stacks:Vec<Vec<char>> = Vec::new();
+        for entry in header {
             let c:Vec<char> = entry.chars().collect();
-            let chunks = c.chunks(4).map(|chunk|
+            let chunks = c.chunks(width + 1).map(|chunk|
                 chunk.into_iter().filter(|c|
                      c.is_ascii_uppercase()
                 ).next() // consume the option Some(<char>)
@@ -120,23 +182,121 @@ impl ElfCrateStack {
         if elf_crate_stacks.is_empty() {
             return Err(ElfCrateErr::EmptyElfCrate);
         }
+        for stack in elf_crate_stacks.iter_mut() {
+            stack.reverse();
+        }
         Ok(ElfCrateStack { crate_stacks: elf_crate_stacks})
     }
 }
+impl fmt::Display for ElfCrateStack {
+    /// Render the stacks in the same drawing format `parse` reads, top row first
+    /// followed by the index footer. Columns are as wide as the widest label.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let width = self.crate_stacks.len().to_string().len().max(3);
+        let height = self.crate_stacks.iter().map(|c| c.len()).max().unwrap_or(0);
+        for level in (0..height).rev() {
+            let row:Vec<String> = self.crate_stacks.iter().map(|c|
+                match c.get(level) {
+                    Some(v) => format!("{:^width$}", format!("[{}]", v), width = width),
+                    None => " ".repeat(width),
+                }
+            ).collect();
+            writeln!(f, "{}", row.join(" "))?;
+        }
+        let footer:Vec<String> = (1..=self.crate_stacks.len()).map(|idx| format!("{:^width$}", idx, width = width)).collect();
+        writeln!(f, "{}", footer.join(" "))
+    }
+}
 
-fn day5(file_path:&str) -> Result<String, ElfCrateErr>
-{
-    let mut bf = BufReader::new(File::open(file_path).expect(file_path));
-    // parse crate stack header of the file
-    let mut elf_crate_stacks = ElfCrateStack::parse(&mut bf)?;
+/// How to replay the crate stack state after each operation
+#[derive(Debug, PartialEq, Eq)]
+enum Replay {
+    /// print each state to stdout
+    Print,
+    /// save each state as `step_NNNNN.txt` in the directory, these parse back with `ElfCrateStack::parse`
+    Save(PathBuf),
+    /// redraw the terminal in place, pausing between steps
+    Animate(Duration),
+}
+impl Replay {
+    /// record the stacks after the given step, step 0 being the initial drawing
+    fn record(&self, step:usize, op:Option<&ElfCrateOperation>, stacks:&ElfCrateStack) -> Result<(), ElfCrateErr> {
+        let title = match op {
+            Some(op) => format!("step {}: {}", step, op),
+            None => format!("step {}: initial", step),
+        };
+        match self {
+            Replay::Print => println!("{}\n{}", title, stacks),
+            Replay::Save(dir) => {
+                std::fs::create_dir_all(dir).map_err(|_| ElfCrateErr::Io)?;
+                std::fs::write(dir.join(format!("step_{:05}.txt", step)), stacks.to_string()).map_err(|_| ElfCrateErr::Io)?;
+            },
+            Replay::Animate(delay) => {
+                // clear the screen and home the cursor before each frame
+                print!("\x1b[2J\x1b[H{}\n{}", title, stacks);
+                std::io::stdout().flush().map_err(|_| ElfCrateErr::Io)?;
+                thread::sleep(*delay);
+            },
+        }
+        Ok(())
+    }
+}
+
+/// Load the crate stack header and the operations that follow it, each with its line number.
+/// All of the operations are validated up front, any that fail to parse or would fail
+/// to perform are returned together as `ElfCrateErr::InvalidOps`.
+fn load<R: Read>(f: &mut BufReader<R>) -> Result<(ElfCrateStack, Vec<(usize, ElfCrateOperation)>), ElfCrateErr> {
+    // the header ends at the first empty line, keep count so we can number the operations
+    let mut header = String::new();
+    let mut line_number = 0;
+    loop {
+        let mut line = String::new();
+        if f.read_line(&mut line).map_err(|_| ElfCrateErr::Malformed)? == 0 {
+            break;
+        }
+        line_number += 1;
+        if line.trim_end_matches(['\r', '\n']).is_empty() {
+            break;
+        }
+        header.push_str(&line);
+    }
+    let elf_crate_stacks = ElfCrateStack::parse(&mut BufReader::new(header.as_bytes()))?;
 
     // remaining lines are our crate operations
-    for line in bf.lines() {
+    let mut ops = Vec::new();
+    let mut errors = Vec::new();
+    for (idx, line) in f.lines().enumerate() {
         let entry = line.map_err(|_| ElfCrateErr::Malformed)?;
+        let line = line_number + idx + 1;
+        if entry.trim().is_empty() {
+            continue;
+        }
+        match entry.parse::<ElfCrateOperation>() {
+            Ok(op) => ops.push((line, op)),
+            Err(err) => errors.push(ElfCrateOpErr{line, err}),
+        }
+    }
+    errors.extend(elf_crate_stacks.validate_ops(&ops));
+    if !errors.is_empty() {
+        errors.sort_by_key(|e| e.line);
+        return Err(ElfCrateErr::InvalidOps(errors));
+    }
+    Ok((elf_crate_stacks, ops))
+}
 
-        if let Ok(op) = entry.parse::<ElfCrateOperation>() {
-            // perform op.count move operations
-            elf_crate_stacks.perform_op(op)?;
+fn day5(file_path:&str, replay:Option<&Replay>) -> Result<String, ElfCrateErr>
+{
+    let mut bf = BufReader::new(File::open(file_path).map_err(|_| ElfCrateErr::Io)?);
+    let (mut elf_crate_stacks, ops) = load(&mut bf)?;
+    if let Some(replay) = replay {
+        replay.record(0, None, &elf_crate_stacks)?;
+    }
+
+    for (step, (_, op)) in ops.iter().enumerate() {
+        // perform op.count move operations
+        elf_crate_stacks.perform_op(op)?;
+        if let Some(replay) = replay {
+            replay.record(step + 1, Some(op), &elf_crate_stacks)?;
         }
     }
     let top_of_the_stacks = elf_crate_stacks.top_of_the_stacks()?;
@@ -147,16 +307,50 @@ fn main()
 {
     let args:Vec<String> = env::args().collect();
     if let None = args.get(1) {
-        println!("Usage: {} <path>", args[0]);
+        println!("Usage: {} <path> [--validate | --replay [<dir>] | --animate [<ms>]]", args[0]);
         std::process::exit(1);
     }
-    println!("{:?}", day5(&args[1]));
+    if args.get(2).map(|a| a.as_str()) == Some("--validate") {
+        let mut bf = BufReader::new(File::open(&args[1]).expect(&args[1]));
+        match load(&mut bf) {
+            Ok((_, ops)) => println!("{} operations ok", ops.len()),
+            Err(ElfCrateErr::InvalidOps(errors)) => {
+                for e in errors {
+                    println!("line {}: {:?}", e.line, e.err);
+                }
+                std::process::exit(1);
+            },
+            Err(e) => {
+                println!("{:?}", e);
+                std::process::exit(1);
+            },
+        }
+        return;
+    }
+    let usage = || {
+        println!("Usage: {} <path> [--validate | --replay [<dir>] | --animate [<ms>]]", args[0]);
+        std::process::exit(1);
+    };
+    let replay = match (args.get(2).map(|a| a.as_str()), args.get(3)) {
+        (None, _) => None,
+        (Some("--replay"), None) => Some(Replay::Print),
+        (Some("--replay"), Some(dir)) => Some(Replay::Save(PathBuf::from(dir))),
+        (Some("--animate"), None) => Some(Replay::Animate(Duration::from_millis(100))),
+        (Some("--animate"), Some(ms)) => match ms.parse::<u64>() {
+            Ok(ms) => Some(Replay::Animate(Duration::from_millis(ms))),
+            Err(_) => return usage(),
+        },
+        _ => return usage(),
+    };
+    println!("{:?}", day5(&args[1], replay.as_ref()));
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{ElfCrateErr, ElfCrateOperation, ElfCrateStack};
+    use super::{load, ElfCrateErr, ElfCrateOpErr, ElfCrateOperation, ElfCrateStack, Replay};
+    use std::io::prelude::*;
     use std::io::BufReader;
+    use std::time::Duration;
 
     #[test]
     fn test_crate_operation_parse() {
@@ -188,12 +382,138 @@ mod tests {
         println!("{:?}", elf_crate_stacks.top_of_the_stacks());
         assert_eq!(elf_crate_stacks.top_of_the_stacks().ok(), Some("NDP".to_string()));
 
-        assert_eq!(elf_crate_stacks.perform_op("move 1 from 2 to 1".parse::<ElfCrateOperation>().unwrap()), Ok(()));
+        assert_eq!(elf_crate_stacks.perform_op(&"move 1 from 2 to 1".parse::<ElfCrateOperation>().unwrap()), Ok(()));
         assert_eq!(elf_crate_stacks.top_of_the_stacks().ok(), Some("DCP".to_string()));
-        assert_eq!(elf_crate_stacks.perform_op("move 3 from 1 to 3".parse::<ElfCrateOperation>().unwrap()), Ok(()));
+        assert_eq!(elf_crate_stacks.perform_op(&"move 3 from 1 to 3".parse::<ElfCrateOperation>().unwrap()), Ok(()));
         assert_eq!(elf_crate_stacks.top_of_the_stacks().unwrap_err(), ElfCrateErr::EmptyElfCrate); // can't top b/c unbalanced
-        assert_eq!(elf_crate_stacks.perform_op("move 2 from 2 to 1".parse::<ElfCrateOperation>().unwrap()), Ok(()));
-        assert_eq!(elf_crate_stacks.perform_op("move 1 from 1 to 2".parse::<ElfCrateOperation>().unwrap()), Ok(()));
+        assert_eq!(elf_crate_stacks.perform_op(&"move 2 from 2 to 1".parse::<ElfCrateOperation>().unwrap()), Ok(()));
+        assert_eq!(elf_crate_stacks.perform_op(&"move 1 from 1 to 2".parse::<ElfCrateOperation>().unwrap()), Ok(()));
         assert_eq!(elf_crate_stacks.top_of_the_stacks().ok(), Some("MCD".to_string()));
     }
+
+    #[test]
+    fn test_elf_crate_stack_display() {
+        let mut bf = BufReader::new(DATA.as_bytes());
+        let mut elf_crate_stacks = ElfCrateStack::parse(&mut bf).unwrap();
+        assert_eq!(elf_crate_stacks.to_string(), DATA.split("\n\n").next().unwrap().to_string() + "\n");
+
+        // every intermediate state round-trips through the parser, including empty stacks
+        for line in bf.lines() {
+            let op = line.unwrap().parse::<ElfCrateOperation>().unwrap();
+            assert_eq!(op.to_string().parse::<ElfCrateOperation>().as_ref(), Ok(&op));
+            elf_crate_stacks.perform_op(&op).unwrap();
+            let rendered = elf_crate_stacks.to_string();
+            let reparsed = ElfCrateStack::parse(&mut BufReader::new(rendered.as_bytes())).unwrap();
+            assert_eq!(reparsed, elf_crate_stacks);
+        }
+        assert_eq!(elf_crate_stacks.to_string(), concat!(
+            "        [D]\n",
+            "        [N]\n",
+            "        [Z]\n",
+            "[M] [C] [P]\n",
+            " 1   2   3 \n",
+        ));
+    }
+
+    #[test]
+    fn test_elf_crate_stack_display_wide() {
+        // labels past 9 keep the footer lined up with the crates
+        let mut elf_crate_stacks = ElfCrateStack { crate_stacks: (0..12).map(|s| vec![(b'A' + s as u8) as char]).collect() };
+        elf_crate_stacks.crate_stacks[11].push('Z');
+        assert_eq!(elf_crate_stacks.to_string(), concat!(
+            "                                            [Z]\n",
+            "[A] [B] [C] [D] [E] [F] [G] [H] [I] [J] [K] [L]\n",
+            " 1   2   3   4   5   6   7   8   9  10  11  12 \n",
+        ));
+        let reparsed = ElfCrateStack::parse(&mut BufReader::new(elf_crate_stacks.to_string().as_bytes())).unwrap();
+        assert_eq!(reparsed, elf_crate_stacks);
+
+        // past 999 the columns widen to fit the label
+        let elf_crate_stacks = ElfCrateStack { crate_stacks: (0..1000).map(|s| if s % 2 == 0 { vec!['X'] } else { Vec::new() }).collect() };
+        let rendered = elf_crate_stacks.to_string();
+        let mut lines = rendered.lines();
+        assert!(lines.next().unwrap().starts_with("[X]       [X]"));
+        assert!(lines.next().unwrap().ends_with("998  999  1000"));
+        let reparsed = ElfCrateStack::parse(&mut BufReader::new(rendered.as_bytes())).unwrap();
+        assert_eq!(reparsed, elf_crate_stacks);
+    }
+
+    #[test]
+    fn test_replay() {
+        let mut elf_crate_stacks = ElfCrateStack::parse(&mut BufReader::new(DATA.as_bytes())).unwrap();
+        let op = "move 1 from 2 to 1".parse::<ElfCrateOperation>().unwrap();
+
+        // saved steps parse back into the same stacks
+        let dir = std::env::temp_dir().join(format!("day5_replay_{}", std::process::id()));
+        let replay = Replay::Save(dir.clone());
+        assert_eq!(replay.record(0, None, &elf_crate_stacks), Ok(()));
+        elf_crate_stacks.perform_op(&op).unwrap();
+        assert_eq!(replay.record(1, Some(&op), &elf_crate_stacks), Ok(()));
+        let saved = std::fs::read_to_string(dir.join("step_00001.txt")).unwrap();
+        assert_eq!(ElfCrateStack::parse(&mut BufReader::new(saved.as_bytes())), Ok(elf_crate_stacks));
+        assert!(dir.join("step_00000.txt").exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        // a zero delay animates without sleeping
+        let elf_crate_stacks = ElfCrateStack::parse(&mut BufReader::new(DATA.as_bytes())).unwrap();
+        assert_eq!(Replay::Animate(Duration::from_millis(0)).record(0, None, &elf_crate_stacks), Ok(()));
+    }
+
+    #[test]
+    fn test_elf_crate_stack_stress() {
+        // a few thousand crates shuffled back and forth between a handful of stacks
+        let stacks = 9;
+        let per_stack = 1000;
+        let mut elf_crate_stacks = ElfCrateStack {
+            crate_stacks: (0..stacks).map(|s| (0..per_stack).map(|i| (b'A' + ((s + i) % 26) as u8) as char).collect()).collect(),
+        };
+        let expected_top:String = elf_crate_stacks.crate_stacks.iter().map(|c| *c.last().unwrap()).collect();
+
+        // moving the same crates out and straight back must be a no-op
+        for i in 0..200_000 {
+            let source_index = i % stacks + 1;
+            let destination_index = (i + 1) % stacks + 1;
+            let count = i % 500 + 1;
+            assert_eq!(elf_crate_stacks.perform_op(&ElfCrateOperation{count, source_index, destination_index}), Ok(()));
+            assert_eq!(elf_crate_stacks.perform_op(&ElfCrateOperation{count, source_index: destination_index, destination_index: source_index}), Ok(()));
+        }
+        assert_eq!(elf_crate_stacks.top_of_the_stacks(), Ok(expected_top));
+        assert_eq!(elf_crate_stacks.crate_stacks.iter().map(|c| c.len()).sum::<usize>(), stacks * per_stack);
+
+        // drain everything onto one stack, order is preserved for part 2 moves
+        let mut elf_crate_stacks = ElfCrateStack { crate_stacks: vec![(0..5000).map(|i| if i == 4999 { 'Z' } else { 'A' }).collect(), Vec::new()] };
+        for _ in 0..5000 {
+            assert_eq!(elf_crate_stacks.perform_op(&ElfCrateOperation{count: 1, source_index: 1, destination_index: 2}), Ok(()));
+            assert_eq!(elf_crate_stacks.perform_op(&ElfCrateOperation{count: 1, source_index: 2, destination_index: 1}), Ok(()));
+        }
+        assert_eq!(elf_crate_stacks.perform_op(&ElfCrateOperation{count: 5000, source_index: 1, destination_index: 2}), Ok(()));
+        assert_eq!(elf_crate_stacks.crate_stacks[1].last(), Some(&'Z'));
+        assert_eq!(elf_crate_stacks.top_of_the_stacks(), Err(ElfCrateErr::EmptyElfCrate));
+    }
+
+    #[test]
+    fn test_elf_crate_operation_validate() {
+        let mut elf_crate_stacks = ElfCrateStack::parse(&mut BufReader::new(DATA.as_bytes())).unwrap();
+        let op = |s:&str| s.parse::<ElfCrateOperation>().unwrap();
+        assert_eq!(elf_crate_stacks.perform_op(&op("move 1 from 0 to 1")), Err(ElfCrateErr::ZeroIndex));
+        assert_eq!(elf_crate_stacks.perform_op(&op("move 1 from 1 to 0")), Err(ElfCrateErr::ZeroIndex));
+        assert_eq!(elf_crate_stacks.perform_op(&op("move 1 from 4 to 1")), Err(ElfCrateErr::InvalidSourceIndex));
+        assert_eq!(elf_crate_stacks.perform_op(&op("move 1 from 1 to 4")), Err(ElfCrateErr::InvalidDestinationIndex));
+        assert_eq!(elf_crate_stacks.perform_op(&op("move 1 from 2 to 2")), Err(ElfCrateErr::SameSourceAndDestination));
+        assert_eq!(elf_crate_stacks.perform_op(&op("move 4 from 2 to 1")), Err(ElfCrateErr::NotEnoughCrates));
+        // nothing moved
+        assert_eq!(elf_crate_stacks.top_of_the_stacks().ok(), Some("NDP".to_string()));
+        assert_eq!(elf_crate_stacks.perform_op(&op("move 3 from 2 to 1")), Ok(()));
+
+        let (_, ops) = load(&mut BufReader::new(DATA.as_bytes())).unwrap();
+        assert_eq!(ops.iter().map(|(line, _)| *line).collect::<Vec<usize>>(), vec![6, 7, 8, 9]);
+
+        let bad = DATA.to_string() + "move 9 from 2 to 1\n\nmove 1 from 0 to 1\ngarbage\nmove 1 from 3 to 3\nmove 1 from 2 to 1\n";
+        assert_eq!(load(&mut BufReader::new(bad.as_bytes())).unwrap_err(), ElfCrateErr::InvalidOps(vec![
+            ElfCrateOpErr{line: 10, err: ElfCrateErr::NotEnoughCrates},
+            ElfCrateOpErr{line: 12, err: ElfCrateErr::ZeroIndex},
+            ElfCrateOpErr{line: 13, err: ElfCrateErr::Malformed},
+            ElfCrateOpErr{line: 14, err: ElfCrateErr::SameSourceAndDestination},
+        ]));
+    }
 }
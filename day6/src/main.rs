@@ -13,59 +13,400 @@ zcfzfwzzqfrljwzlrfnpqdbhtmscgvjw: first marker after character 11
 
 */
 use std::env;
-use std::collections::HashSet;
-use std::fs::read_to_string;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader, Read};
+use std::num::NonZeroUsize;
 
-fn day6(input_line:&str, msg_len:usize) -> Option<usize>
-{
-    let input_line_len = input_line.len();
-
-    let mut window:HashSet<char> = HashSet::with_capacity(4);
-    for offset in 0..input_line_len {
-        if offset + msg_len < input_line_len {
-            window.extend(input_line[offset..offset+msg_len].chars());
-            if window.len() == msg_len {
-                return Some(offset+msg_len);
+/// Incremental start marker detector
+///
+/// Tracks where each character was last seen and the start of the current run of
+/// distinct characters, so each character is handled in O(1) no matter the marker length.
+struct MarkerDetector {
+    msg_len: usize,
+    last_seen: HashMap<char, usize>,
+    window_start: usize,
+    position: usize,
+}
+impl MarkerDetector {
+    fn new(msg_len:usize) -> Self {
+        Self { msg_len, last_seen: HashMap::new(), window_start: 0, position: 0 }
+    }
+
+    /// Feed the next character, returning the marker position (count of characters consumed)
+    /// if the last msg_len characters are all different
+    fn push(&mut self, c:char) -> Option<usize> {
+        if let Some(prev) = self.last_seen.insert(c, self.position) {
+            // a repeat inside the window, the run of distinct characters restarts after it
+            if prev >= self.window_start {
+                self.window_start = prev + 1;
+            }
+        }
+        self.position += 1;
+        if self.position - self.window_start >= self.msg_len {
+            Some(self.position)
+        } else {
+            None
+        }
+    }
+}
+
+/// Decode a byte stream into chars, invalid UTF-8 becomes U+FFFD rather than an error
+struct Utf8Chars<R: Read> {
+    bytes: io::Bytes<BufReader<R>>,
+    pending: Option<u8>,
+    /// bytes consumed by the chars returned so far
+    offset: usize,
+}
+impl<R: Read> Utf8Chars<R> {
+    fn new(reader:R) -> Self {
+        Self { bytes: BufReader::new(reader).bytes(), pending: None, offset: 0 }
+    }
+}
+impl<R: Read> Iterator for Utf8Chars<R> {
+    type Item = io::Result<char>;
+    fn next(&mut self) -> Option<Self::Item> {
+        let lead = match self.pending.take() {
+            Some(b) => b,
+            None => match self.bytes.next()? {
+                Ok(b) => b,
+                Err(e) => return Some(Err(e)),
+            },
+        };
+        self.offset += 1;
+        let width = match lead {
+            0x00..=0x7f => return Some(Ok(lead as char)),
+            0xc0..=0xdf => 2,
+            0xe0..=0xef => 3,
+            0xf0..=0xf7 => 4,
+            _ => return Some(Ok(char::REPLACEMENT_CHARACTER)),
+        };
+        let mut buf = vec![lead];
+        while buf.len() < width {
+            match self.bytes.next() {
+                Some(Ok(b)) if (0x80..=0xbf).contains(&b) => {
+                    self.offset += 1;
+                    buf.push(b);
+                },
+                // not a continuation byte, it starts the next character
+                Some(Ok(b)) => {
+                    self.pending = Some(b);
+                    break;
+                },
+                Some(Err(e)) => return Some(Err(e)),
+                None => break,
+            }
+        }
+        Some(Ok(std::str::from_utf8(&buf).ok().and_then(|s| s.chars().next()).unwrap_or(char::REPLACEMENT_CHARACTER)))
+    }
+}
+
+/// Every marker position for the given length in the stream, line endings are skipped.
+/// An empty marker would match before every character, so the length can't be 0.
+fn markers<R: Read>(reader:R, msg_len:NonZeroUsize) -> impl Iterator<Item=io::Result<usize>> {
+    let mut detector = MarkerDetector::new(msg_len.get());
+    Utf8Chars::new(reader).filter_map(move |c| match c {
+        Ok('\n') | Ok('\r') => None,
+        Ok(c) => detector.push(c).map(Ok),
+        Err(e) => Some(Err(e)),
+    })
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum SignalErr {
+    /// a marker has to be at least one character long
+    ZeroMarkerLength,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum FrameKind {
+    Packet,
+    Message,
+}
+
+/// A decoded frame, offsets are in bytes from the start of the stream
+#[derive(Debug, PartialEq, Eq)]
+struct Frame {
+    kind: FrameKind,
+    /// where the start marker of this frame begins
+    marker: usize,
+    /// where the payload begins, right after the marker
+    start: usize,
+    payload: String,
+}
+impl Frame {
+    fn to_json(&self) -> String {
+        let mut payload = String::with_capacity(self.payload.len());
+        for c in self.payload.chars() {
+            match c {
+                '"' => payload.push_str("\\\""),
+                '\\' => payload.push_str("\\\\"),
+                c if (c as u32) < 0x20 => payload.push_str(&format!("\\u{:04x}", c as u32)),
+                c => payload.push(c),
             }
-            window.clear();
         }
+        format!("{{\"kind\":\"{}\",\"marker\":{},\"start\":{},\"payload\":\"{}\"}}", self.kind, self.marker, self.start, payload)
+    }
+}
+impl fmt::Display for FrameKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FrameKind::Packet => write!(f, "packet"),
+            FrameKind::Message => write!(f, "message"),
+        }
+    }
+}
+impl fmt::Display for Frame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} marker@{} start@{}: {}", self.kind, self.marker, self.start, self.payload)
     }
-    None
+}
+
+/// Split a datastream into frames using the markers as delimiters
+///
+/// Anything before the first start-of-packet marker is noise. A packet runs until the next
+/// start-of-message marker, and that message runs until the next start-of-packet marker, so
+/// packets and messages alternate. Each marker is searched for only after the previous one,
+/// markers never overlap, and the last frame is closed by the end of the stream.
+struct SignalDecoder<R: Read> {
+    chars: Utf8Chars<R>,
+    packet_len: usize,
+    message_len: usize,
+    looking_for: FrameKind,
+    detector: MarkerDetector,
+    /// byte offsets of the most recent chars, enough to locate the start of a marker
+    recent: VecDeque<usize>,
+    current: Option<Frame>,
+}
+impl<R: Read> SignalDecoder<R> {
+    fn new(reader:R, packet_len:usize, message_len:usize) -> Result<Self, SignalErr> {
+        if packet_len == 0 || message_len == 0 {
+            return Err(SignalErr::ZeroMarkerLength);
+        }
+        Ok(Self {
+            chars: Utf8Chars::new(reader),
+            packet_len,
+            message_len,
+            looking_for: FrameKind::Packet,
+            detector: MarkerDetector::new(packet_len),
+            recent: VecDeque::with_capacity(packet_len.max(message_len)),
+            current: None,
+        })
+    }
+}
+impl<R: Read> Iterator for SignalDecoder<R> {
+    type Item = io::Result<Frame>;
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let offset = self.chars.offset;
+            let c = match self.chars.next() {
+                Some(Ok(c)) => c,
+                Some(Err(e)) => return Some(Err(e)),
+                None => return self.current.take().map(Ok),
+            };
+            if c == '\n' || c == '\r' {
+                continue;
+            }
+            if self.recent.len() == self.packet_len.max(self.message_len) {
+                self.recent.pop_front();
+            }
+            self.recent.push_back(offset);
+            if let Some(frame) = self.current.as_mut() {
+                frame.payload.push(c);
+            }
+
+            if self.detector.push(c).is_none() {
+                continue;
+            }
+            let (marker_len, next_kind, next_len) = match self.looking_for {
+                FrameKind::Packet => (self.packet_len, FrameKind::Message, self.message_len),
+                FrameKind::Message => (self.message_len, FrameKind::Packet, self.packet_len),
+            };
+            // the marker chars belong to the new frame, not the payload of the finished one
+            let mut finished = self.current.take();
+            if let Some(frame) = finished.as_mut() {
+                for _ in 0..marker_len {
+                    frame.payload.pop();
+                }
+            }
+            self.current = Some(Frame {
+                kind: self.looking_for,
+                marker: self.recent[self.recent.len() - marker_len],
+                start: self.chars.offset,
+                payload: String::new(),
+            });
+            self.looking_for = next_kind;
+            self.detector = MarkerDetector::new(next_len);
+            if let Some(frame) = finished {
+                return Some(Ok(frame));
+            }
+        }
+    }
+}
+
+/// the puzzle's start-of-packet and start-of-message marker lengths
+static PACKET_MARKER_LEN:NonZeroUsize = NonZeroUsize::new(4).unwrap();
+static MESSAGE_MARKER_LEN:NonZeroUsize = NonZeroUsize::new(14).unwrap();
+
+/// First marker position for the given length in the stream, read errors are passed on
+fn day6<R: Read>(input:R, msg_len:NonZeroUsize) -> io::Result<Option<usize>>
+{
+    markers(input, msg_len).next().transpose()
 }
 
 fn main()
 {
     let args:Vec<String> = env::args().collect();
-    if let None = args.get(1) {
-        println!("Usage: {} <path>", args[0]);
+    if args.get(1).is_none() {
+        println!("Usage: {} <path> [--all <marker length> | --frames [text|json] [<packet length> <message length>]]", args[0]);
         std::process::exit(1);
     }
-    if let Ok(line) = read_to_string(&args[1]) {
-        if let Some(first_marker) = day6(&line, 4) {
-            println!("first marker is {} for part1", first_marker);
+    if args.get(2).map(|a| a.as_str()) == Some("--frames") {
+        let json = args.get(3).map(|a| a.as_str()) == Some("json");
+        let packet_len:usize = args.get(4).and_then(|l| l.parse().ok()).unwrap_or(4);
+        let message_len:usize = args.get(5).and_then(|l| l.parse().ok()).unwrap_or(14);
+        let decoder = match SignalDecoder::new(File::open(&args[1]).expect(&args[1]), packet_len, message_len) {
+            Ok(decoder) => decoder,
+            Err(e) => {
+                println!("{:?}", e);
+                std::process::exit(1);
+            },
+        };
+        let frames = match decoder.collect::<io::Result<Vec<Frame>>>() {
+            Ok(frames) => frames,
+            Err(e) => {
+                println!("{:?}", e);
+                std::process::exit(1);
+            },
+        };
+        if json {
+            println!("[{}]", frames.iter().map(|f| f.to_json()).collect::<Vec<String>>().join(",\n "));
+        } else {
+            for frame in frames {
+                println!("{}", frame);
+            }
         }
-        if let Some(first_marker) = day6(&line, 14) {
-            println!("first marker is {} for part2", first_marker);
+        return;
+    }
+    if args.get(2).map(|a| a.as_str()) == Some("--all") {
+        let msg_len = match NonZeroUsize::new(args.get(3).and_then(|l| l.parse().ok()).unwrap_or(4)) {
+            Some(msg_len) => msg_len,
+            None => {
+                println!("{:?}", SignalErr::ZeroMarkerLength);
+                std::process::exit(1);
+            },
+        };
+        for marker in markers(File::open(&args[1]).expect(&args[1]), msg_len) {
+            match marker {
+                Ok(position) => println!("{}", position),
+                Err(e) => {
+                    println!("{:?}", e);
+                    std::process::exit(1);
+                },
+            }
+        }
+        return;
+    }
+
+    for (part, msg_len) in [(1, PACKET_MARKER_LEN), (2, MESSAGE_MARKER_LEN)].iter() {
+        match day6(File::open(&args[1]).expect(&args[1]), *msg_len) {
+            Ok(Some(first_marker)) => println!("first marker is {} for part{}", first_marker, part),
+            Ok(None) => println!("no marker for part{}", part),
+            Err(e) => {
+                println!("{:?}", e);
+                std::process::exit(1);
+            },
         }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::day6;
+    use super::{day6, markers, Frame, FrameKind, SignalDecoder, SignalErr};
+    use std::io::{self, Read};
+    use std::num::NonZeroUsize;
+
+    fn len(v:usize) -> NonZeroUsize {
+        NonZeroUsize::new(v).unwrap()
+    }
 
     #[test]
     fn test_day6() {
-        assert_eq!(day6("bvwbjplbgvbhsrlpgdmjqwftvncz", 4), Some(5));
-        assert_eq!(day6("nppdvjthqldpwncqszvftbrmjlhg", 4), Some(6));
-        assert_eq!(day6("nznrnfrfntjfmvfwmzdfjlvtqnbhcprsg", 4), Some(10));
-        assert_eq!(day6("zcfzfwzzqfrljwzlrfnpqdbhtmscgvjw", 4), Some(11));
+        assert_eq!(day6("bvwbjplbgvbhsrlpgdmjqwftvncz".as_bytes(), len(4)).unwrap(), Some(5));
+        assert_eq!(day6("nppdvjthqldpwncqszvftbrmjlhg".as_bytes(), len(4)).unwrap(), Some(6));
+        assert_eq!(day6("nznrnfrfntjfmvfwmzdfjlvtqnbhcprsg".as_bytes(), len(4)).unwrap(), Some(10));
+        assert_eq!(day6("zcfzfwzzqfrljwzlrfnpqdbhtmscgvjw".as_bytes(), len(4)).unwrap(), Some(11));
+
+        assert_eq!(day6("mjqjpqmgbljsphdztnvjfqwrcgsmlb".as_bytes(), len(14)).unwrap(), Some(19));
+        assert_eq!(day6("bvwbjplbgvbhsrlpgdmjqwftvncz".as_bytes(), len(14)).unwrap(), Some(23));
+        assert_eq!(day6("nppdvjthqldpwncqszvftbrmjlhg".as_bytes(), len(14)).unwrap(), Some(23));
+        assert_eq!(day6("nznrnfrfntjfmvfwmzdfjlvtqnbhcprsg".as_bytes(), len(14)).unwrap(), Some(29));
+        assert_eq!(day6("zcfzfwzzqfrljwzlrfnpqdbhtmscgvjw".as_bytes(), len(14)).unwrap(), Some(26));
+
+        // the final window counts too
+        assert_eq!(day6("aabcd".as_bytes(), len(4)).unwrap(), Some(5));
+        assert_eq!(day6("abc".as_bytes(), len(4)).unwrap(), None);
+        // non-ASCII is counted per character, not per byte
+        assert_eq!(day6("ééaß€ö".as_bytes(), len(4)).unwrap(), Some(5));
+        assert_eq!(day6("a\u{fffd}\u{fffd}b".as_bytes(), len(2)).unwrap(), Some(2));
 
-        assert_eq!(day6("mjqjpqmgbljsphdztnvjfqwrcgsmlb", 14), Some(19));
-        assert_eq!(day6("bvwbjplbgvbhsrlpgdmjqwftvncz", 14), Some(23));
-        assert_eq!(day6("nppdvjthqldpwncqszvftbrmjlhg", 14), Some(23));
-        assert_eq!(day6("nznrnfrfntjfmvfwmzdfjlvtqnbhcprsg", 14), Some(29));
-        assert_eq!(day6("zcfzfwzzqfrljwzlrfnpqdbhtmscgvjw", 14), Some(26));
+        // a read error is reported rather than looking like a missing marker
+        struct Broken;
+        impl Read for Broken {
+            fn read(&mut self, _:&mut [u8]) -> io::Result<usize> {
+                Err(io::Error::other("broken"))
+            }
+        }
+        assert_eq!(day6(Broken, len(4)).unwrap_err().kind(), io::ErrorKind::Other);
+    }
+
+    #[test]
+    fn test_markers() {
+        let all = |s:&str, msg_len| markers(s.as_bytes(), len(msg_len)).map(|m| m.unwrap()).collect::<Vec<usize>>();
+        assert_eq!(all("aabcabbcd\n", 3), vec![4, 5, 6, 9]);
+        assert_eq!(all("abcdefg", 7), vec![7]);
+        assert_eq!(all("aaaa", 1), vec![1, 2, 3, 4]);
+        // invalid UTF-8 decodes to the replacement character
+        assert_eq!(markers(&[b'a', 0xff, b'b', 0xc3][..], len(3)).map(|m| m.unwrap()).collect::<Vec<usize>>(), vec![3]);
+
+        // a long stream is handled in one pass
+        let stream:String = (0..200_000).map(|i| (b'a' + (i % 20) as u8) as char).collect();
+        assert_eq!(markers(stream.as_bytes(), len(14)).count(), 200_000 - 13);
+        assert_eq!(markers(stream.as_bytes(), len(21)).count(), 0);
+    }
 
+    #[test]
+    fn test_signal_decoder() {
+        let frames = |s:&str, packet_len, message_len| SignalDecoder::new(s.as_bytes(), packet_len, message_len).unwrap().map(|f| f.unwrap()).collect::<Vec<Frame>>();
+        assert_eq!(frames("aabaabcaab", 2, 3), vec![
+            Frame{kind: FrameKind::Packet, marker: 1, start: 3, payload: "a".to_string()},
+            Frame{kind: FrameKind::Message, marker: 4, start: 7, payload: "a".to_string()},
+            Frame{kind: FrameKind::Packet, marker: 8, start: 10, payload: "".to_string()},
+        ]);
+        // the first start-of-packet marker matches day6, the message marker is searched for after it
+        let decoded = frames("bvwbjplbgvbhsrlpgdmjqwftvncz\n", 4, 14);
+        assert_eq!(decoded[0].start, day6("bvwbjplbgvbhsrlpgdmjqwftvncz".as_bytes(), len(4)).unwrap().unwrap());
+        assert_eq!(decoded, vec![
+            Frame{kind: FrameKind::Packet, marker: 1, start: 5, payload: "plbg".to_string()},
+            Frame{kind: FrameKind::Message, marker: 9, start: 23, payload: "".to_string()},
+            Frame{kind: FrameKind::Packet, marker: 23, start: 27, payload: "z".to_string()},
+        ]);
+        assert_eq!(frames("xxabcyyyyabcdefzzzzzz", 3, 6), vec![
+            Frame{kind: FrameKind::Packet, marker: 1, start: 4, payload: "cyyy".to_string()},
+            Frame{kind: FrameKind::Message, marker: 8, start: 14, payload: "fzzzzzz".to_string()},
+        ]);
+        assert!(frames("aaaa", 4, 14).is_empty());
+        // a zero length marker is rejected up front
+        assert_eq!(SignalDecoder::new("abc".as_bytes(), 0, 14).err(), Some(SignalErr::ZeroMarkerLength));
+        assert_eq!(SignalDecoder::new("abc".as_bytes(), 4, 0).err(), Some(SignalErr::ZeroMarkerLength));
+        // byte offsets account for multi-byte chars
+        assert_eq!(frames("ééaß€xxy", 3, 2), vec![
+            Frame{kind: FrameKind::Packet, marker: 2, start: 7, payload: "".to_string()},
+            Frame{kind: FrameKind::Message, marker: 7, start: 11, payload: "xy".to_string()},
+        ]);
+        assert_eq!(frames("ab", 2, 2)[0].to_json(), r#"{"kind":"packet","marker":0,"start":2,"payload":""}"#);
+        assert_eq!(Frame{kind: FrameKind::Message, marker: 0, start: 2, payload: "a\"\\\t".to_string()}.to_json(),
+            r#"{"kind":"message","marker":0,"start":2,"payload":"a\"\\\u0009"}"#);
     }
 }
@@ -46,191 +46,589 @@ Find all of the directories with a total size of at most 100000. What is the sum
 
 */
 use std::env;
+use std::fmt::Write as _;
 use std::fs::File;
 use std::io::prelude::*;
 use std::io::BufReader;
-use std::path::PathBuf;
+use std::path::{Component, Path, PathBuf};
 use std::collections::HashMap;
 
 static ROOT_PATH:&str = "/";
-static CD_CMD:&str = "$ cd ";
-static CD_PARENT:&str = "..";
+static CMD_PREFIX:&str = "$ ";
+static CD_CMD:&str = "cd";
 static DIR_PREFIX:&str = "dir ";
-static LS_CMD:&str = "$ ls";
+static LS_CMD:&str = "ls";
+
+/// Index of a directory in the `Day7Fs` arena
+type DirId = usize;
+
+#[derive(Debug)]
+enum Day7Entry {
+    Dir(DirId),
+    File(String, usize),
+}
 
 #[derive(Debug)]
 struct Day7Dir {
     path: PathBuf,
-    files: HashMap<PathBuf, usize>,
-    directories: HashMap<PathBuf, Day7Dir>,
+    parent: Option<DirId>,
+    /// subdirectories and files in the order they were listed
+    entries: Vec<Day7Entry>,
+    /// size of everything in and below this directory, kept up to date as files are added
+    total: usize,
 }
 impl Day7Dir {
-    fn new(path: &PathBuf) -> Self{
-        Self { path: path.clone(), files: HashMap::new(), directories: HashMap::new()}
+    fn new(path: &Path, parent: Option<DirId>) -> Self{
+        Self { path: path.to_path_buf(), parent, entries: Vec::new(), total: 0}
     }
     fn total(&self) -> usize {
-        self.files.iter().map(|(_, s)| s).sum::<usize>() + self.directories.iter().map(|(_, d)| d.total()).sum::<usize>()
+        self.total
+    }
+    fn name(&self) -> &str {
+        self.path.file_name().and_then(|n| n.to_str()).unwrap_or(ROOT_PATH)
     }
+    fn directories(&self) -> impl DoubleEndedIterator<Item=DirId> + '_ {
+        self.entries.iter().filter_map(|e| match e {
+            Day7Entry::Dir(id) => Some(*id),
+            Day7Entry::File(..) => None,
+        })
+    }
+}
 
+/// Filesystem tree stored as an arena of directories with parent links
+#[derive(Debug)]
+struct Day7Fs {
+    dirs: Vec<Day7Dir>,
+    /// directory path to arena index, so the cwd is found without searching the tree
+    dir_index: HashMap<PathBuf, DirId>,
+    /// file path to size, so relisting a directory does not count a file twice
+    file_index: HashMap<PathBuf, usize>,
 }
+impl Day7Fs {
+    const ROOT: DirId = 0;
+
+    fn new() -> Self {
+        let root = PathBuf::from(ROOT_PATH);
+        let mut dir_index = HashMap::new();
+        dir_index.insert(root.clone(), Self::ROOT);
+        Self { dirs: vec![Day7Dir::new(&root, None)], dir_index, file_index: HashMap::new() }
+    }
 
-/*
-TODO
-    let root_iter = Day7DirIter::new(&root);
-    for dir in root_iter {
-        println!("{:?}", dir.path);
+    fn dir(&self, id:DirId) -> &Day7Dir {
+        &self.dirs[id]
+    }
+
+    fn find_dir(&self, path:&Path) -> Option<DirId> {
+        self.dir_index.get(path).copied()
+    }
+
+    fn file_size(&self, path:&Path) -> Option<usize> {
+        self.file_index.get(path).copied()
+    }
+
+    /// add the named subdirectory, returning the existing one if it was already listed
+    fn add_dir(&mut self, parent:DirId, name:&str) -> DirId {
+        let path = self.dirs[parent].path.join(name);
+        if let Some(&id) = self.dir_index.get(&path) {
+            return id;
+        }
+        let id = self.dirs.len();
+        self.dirs.push(Day7Dir::new(&path, Some(parent)));
+        self.dirs[parent].entries.push(Day7Entry::Dir(id));
+        self.dir_index.insert(path, id);
+        id
+    }
+
+    /// add the named file, updating the cached totals of the directory and its ancestors
+    fn add_file(&mut self, dir:DirId, name:&str, size:usize) {
+        let path = self.dirs[dir].path.join(name);
+        let previous = match self.file_index.insert(path, size) {
+            Some(previous) => {
+                for entry in self.dirs[dir].entries.iter_mut() {
+                    match entry {
+                        Day7Entry::File(n, s) if n == name => *s = size,
+                        _ => {},
+                    }
+                }
+                previous
+            },
+            None => {
+                self.dirs[dir].entries.push(Day7Entry::File(name.to_string(), size));
+                0
+            },
+        };
+        let mut next = Some(dir);
+        while let Some(id) = next {
+            self.dirs[id].total = self.dirs[id].total - previous + size;
+            next = self.dirs[id].parent;
+        }
+    }
+
+    /// depth first walk of every directory, starting with the root
+    fn iter(&self) -> Day7DirIter<'_> {
+        Day7DirIter { fs: self, stack: vec![Self::ROOT] }
+    }
+
+    /// the indented `- / (dir)` layout from the puzzle description
+    fn render_tree(&self) -> String {
+        let mut out = String::new();
+        self.render_tree_dir(Self::ROOT, 0, &mut out);
+        out
+    }
+    fn render_tree_dir(&self, id:DirId, depth:usize, out:&mut String) {
+        let dir = self.dir(id);
+        let _ = writeln!(out, "{}- {} (dir)", "  ".repeat(depth), dir.name());
+        for entry in &dir.entries {
+            match entry {
+                Day7Entry::Dir(subdir) => self.render_tree_dir(*subdir, depth + 1, out),
+                Day7Entry::File(name, size) => {
+                    let _ = writeln!(out, "{}- {} (file, size={})", "  ".repeat(depth + 1), name, size);
+                },
+            }
+        }
+    }
+
+    /// `du` style report of every directory, largest first
+    fn render_du(&self) -> String {
+        let mut dirs:Vec<&Day7Dir> = self.iter().collect();
+        dirs.sort_by(|a, b| b.total().cmp(&a.total()).then_with(|| a.path.cmp(&b.path)));
+        dirs.iter().map(|d| format!("{}\t{}\n", d.total(), d.path.display())).collect()
+    }
+
+    /// nested JSON objects, directories carry their total size and entries
+    fn render_json(&self) -> String {
+        let mut out = String::new();
+        self.render_json_dir(Self::ROOT, &mut out);
+        out.push('\n');
+        out
+    }
+    fn render_json_dir(&self, id:DirId, out:&mut String) {
+        let dir = self.dir(id);
+        let _ = write!(out, "{{\"name\":\"{}\",\"type\":\"dir\",\"size\":{},\"entries\":[", json_escape(dir.name()), dir.total());
+        for (idx, entry) in dir.entries.iter().enumerate() {
+            if idx > 0 {
+                out.push(',');
+            }
+            match entry {
+                Day7Entry::Dir(subdir) => self.render_json_dir(*subdir, out),
+                Day7Entry::File(name, size) => {
+                    let _ = write!(out, "{{\"name\":\"{}\",\"type\":\"file\",\"size\":{}}}", json_escape(name), size);
+                },
+            }
+        }
+        out.push_str("]}");
+    }
+
+    /// a `$ cd`/`$ ls` transcript that `parse` turns back into this tree
+    fn render_transcript(&self) -> String {
+        let mut out = String::new();
+        self.render_transcript_dir(Self::ROOT, &mut out);
+        out
+    }
+    fn render_transcript_dir(&self, id:DirId, out:&mut String) {
+        let dir = self.dir(id);
+        let _ = writeln!(out, "{}{} {}", CMD_PREFIX, CD_CMD, dir.name());
+        let _ = writeln!(out, "{}{}", CMD_PREFIX, LS_CMD);
+        for entry in &dir.entries {
+            let _ = match entry {
+                Day7Entry::Dir(subdir) => writeln!(out, "{}{}", DIR_PREFIX, self.dir(*subdir).name()),
+                Day7Entry::File(name, size) => writeln!(out, "{} {}", size, name),
+            };
+        }
+        for subdir in dir.directories() {
+            self.render_transcript_dir(subdir, out);
+        }
+        if dir.parent.is_some() {
+            let _ = writeln!(out, "{}{} ..", CMD_PREFIX, CD_CMD);
+        }
     }
-*/
-/*
-struct Day7DirIter <'d> {
-    data: &'d Day7Dir,
-    didself: bool,
-    iter: Iter<'d, PathBuf, Day7Dir>,
 }
 
-impl<'a> Day7DirIter<'a> {
-    fn new(data: &'a Day7Dir) -> Self {
-        Self { data: data, didself: false, iter: data.directories.iter()}
+/// Escape a string for a JSON literal, every control character below U+0020 becomes `\u00XX`
+fn json_escape(v:&str) -> String {
+    let mut escaped = String::with_capacity(v.len());
+    for c in v.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
     }
+    escaped
+}
+
+struct Day7DirIter<'d> {
+    fs: &'d Day7Fs,
+    stack: Vec<DirId>,
 }
 
 impl<'i> Iterator for Day7DirIter<'i> {
     type Item = &'i Day7Dir;
 
     fn next(&mut self) -> Option<Self::Item> {
-        match self.didself {
-            true => {
-                match self.iter.next() {
-                    Some((_, dir)) => Some(dir),
-                    None => None,
-                }
-            },
-            false => {
-                self.didself = true;
-                Some(self.data)
-            }
-        }
+        let dir = self.fs.dir(self.stack.pop()?);
+        // reversed so the subdirectories come out in the order they were listed
+        self.stack.extend(dir.directories().rev());
+        Some(dir)
     }
 }
-*/
 
-fn find_dir<'a>(root:&'a mut Day7Dir, path:&PathBuf) -> Option<&'a mut Day7Dir> {
-    if root.path == *path {
-        return Some(root);
-    } else {
-        for (_, dir) in &mut root.directories {
-            match find_dir(dir, path) {
-                Some(d) => {return Some(d)},
-                None => {},
+#[derive(Debug, PartialEq, Eq)]
+enum Day7Err {
+    Io,
+    /// not `cd <path>` or `ls`
+    UnknownCommand { line: usize, command: String },
+    /// cd into a directory that was never listed
+    UnknownDirectory { line: usize, path: PathBuf },
+    /// listing output that does not follow an ls
+    UnexpectedOutput { line: usize },
+    /// listing output that is not `dir <name>` or `<size> <name>`
+    InvalidEntry { line: usize },
+    /// listed again as a different kind of entry or with a different size
+    ConflictingEntry { line: usize, path: PathBuf },
+    /// even deleting everything does not free the space needed
+    CannotFreeEnough { needed: usize, available: usize },
+}
+
+/// Set of sums below a limit, as a bitset
+#[derive(Debug, Clone)]
+struct SumSet {
+    words: Vec<u64>,
+    limit: usize,
+}
+impl SumSet {
+    /// just the empty sum
+    fn new(limit:usize) -> Self {
+        let mut words = vec![0; limit / 64 + 1];
+        words[0] = 1;
+        Self { words, limit }
+    }
+
+    fn contains(&self, v:usize) -> bool {
+        v < self.limit && self.words[v / 64] & (1 << (v % 64)) != 0
+    }
+
+    /// add every sum of other plus shift, returning the sums that were not already in the set
+    fn union_shifted(&mut self, other:&SumSet, shift:usize) -> Vec<usize> {
+        let (word_shift, bit_shift) = (shift / 64, shift % 64);
+        let mut added = Vec::new();
+        for idx in word_shift..self.words.len() {
+            let src = idx - word_shift;
+            let mut v = other.words[src] << bit_shift;
+            if bit_shift > 0 && src > 0 {
+                v |= other.words[src - 1] >> (64 - bit_shift);
+            }
+            // drop anything shifted past the limit
+            if idx == self.words.len() - 1 {
+                v &= (1u64 << (self.limit % 64)) - 1;
+            }
+            let mut new = v & !self.words[idx];
+            self.words[idx] |= v;
+            while new != 0 {
+                added.push(idx * 64 + new.trailing_zeros() as usize);
+                new &= new - 1;
             }
         }
+        added
     }
-    None
 }
 
-fn find_big_ones<'a>(root:&'a Day7Dir, collector:&mut Vec<&'a Day7Dir>)
-{
-    if root.total() <= 100000 {
-        collector.push(root);
-    }
-    for (_, dir) in &root.directories {
-        find_big_ones(&dir, collector);
+/// Plans which directories to delete to make room for an update
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct DeletionPlanner {
+    /// total disk space
+    capacity: usize,
+    /// unused space the update needs
+    required: usize,
+    /// directories at most this size are reported as small
+    small_threshold: usize,
+}
+impl Default for DeletionPlanner {
+    fn default() -> Self {
+        Self { capacity: 70000000, required: 30000000, small_threshold: 100000 }
     }
 }
+impl DeletionPlanner {
+    /// space that still has to be freed, 0 if there is already enough unused space
+    fn space_needed(&self, fs:&Day7Fs) -> usize {
+        (self.required + fs.dir(Day7Fs::ROOT).total()).saturating_sub(self.capacity)
+    }
 
-fn find_delete_candidates<'a>(root:&'a Day7Dir, collector:&mut Vec<&'a Day7Dir>, threshhold:usize)
-{
-    if root.total() >= threshhold {
-        collector.push(root);
+    fn small_dirs<'a>(&self, fs:&'a Day7Fs) -> Vec<&'a Day7Dir> {
+        fs.iter().filter(|d| d.total() <= self.small_threshold).collect()
     }
-    for (_, dir) in &root.directories {
-        find_delete_candidates(&dir, collector, threshhold);
+
+    /// every directory big enough to free the needed space on its own, smallest first
+    fn delete_candidates<'a>(&self, fs:&'a Day7Fs) -> Vec<&'a Day7Dir> {
+        let needed = self.space_needed(fs);
+        let mut candidates:Vec<&Day7Dir> = fs.iter().filter(|d| d.total() >= needed).collect();
+        candidates.sort_by_key(|d| d.total());
+        candidates
     }
+
+    /// the smallest single directory that frees enough space, None if nothing needs deleting
+    fn smallest_single<'a>(&self, fs:&'a Day7Fs) -> Result<Option<&'a Day7Dir>, Day7Err> {
+        let needed = self.space_needed(fs);
+        if needed == 0 {
+            return Ok(None);
+        }
+        match self.delete_candidates(fs).first() {
+            Some(dir) => Ok(Some(*dir)),
+            None => Err(Day7Err::CannotFreeEnough { needed, available: fs.dir(Day7Fs::ROOT).total() }),
+        }
+    }
+
+    /// the set of non-nested directories that frees enough space while deleting the least,
+    /// preferring outer directories. Empty if nothing needs deleting.
+    ///
+    /// Any directory big enough on its own is only worth deleting alone, so this is a subset sum
+    /// over the smaller directories, bounded by the best single directory.
+    fn smallest_set<'a>(&self, fs:&'a Day7Fs) -> Result<Vec<&'a Day7Dir>, Day7Err> {
+        let needed = self.space_needed(fs);
+        let best_single = match self.smallest_single(fs)? {
+            Some(dir) => dir,
+            None => return Ok(Vec::new()),
+        };
+
+        // preorder with the end of each subtree, taking a directory skips over its subtree
+        let mut order:Vec<DirId> = Vec::with_capacity(fs.dirs.len());
+        let mut stack = vec![Day7Fs::ROOT];
+        while let Some(id) = stack.pop() {
+            order.push(id);
+            stack.extend(fs.dir(id).directories().rev());
+        }
+        let mut subtree_end = vec![0; order.len()];
+        let mut subtree_size = vec![1; fs.dirs.len()];
+        for (idx, &id) in order.iter().enumerate().rev() {
+            if let Some(parent) = fs.dir(id).parent {
+                subtree_size[parent] += subtree_size[id];
+            }
+            subtree_end[idx] = idx + subtree_size[id];
+        }
+        let takeable:Vec<Option<usize>> = order.iter().map(|&id| {
+            let total = fs.dir(id).total();
+            if total > 0 && total < needed { Some(total) } else { None }
+        }).collect();
+
+        // sums that can be freed from each suffix of the preorder, only sums that would beat
+        // the best single directory matter. Each suffix only adds to the one after it, so a single
+        // set is rolled towards the front and every sum is recorded once, against the latest
+        // position it can be freed from, 0 if none. Full copies are only kept for the ends of the
+        // subtrees being walked, taking a directory continues from the end of its subtree.
+        let limit = best_single.total();
+        let mut is_end = vec![false; order.len() + 1];
+        for &end in &subtree_end {
+            is_end[end] = true;
+        }
+        let mut reachable = SumSet::new(limit);
+        let mut ends:Vec<(usize, SumSet)> = Vec::new();
+        let mut reachable_from = vec![0; limit];
+        reachable_from[0] = order.len();
+        for idx in (0..order.len()).rev() {
+            if is_end[idx + 1] {
+                ends.push((idx + 1, reachable.clone()));
+            }
+            // anything ending inside this subtree has already been used
+            while ends.last().is_some_and(|(end, _)| *end < subtree_end[idx]) {
+                ends.pop();
+            }
+            if let (Some(total), Some((_, after))) = (takeable[idx], ends.last()) {
+                for v in reachable.union_shifted(after, total) {
+                    reachable_from[v] = idx;
+                }
+            }
+        }
+        let mut remaining = match (needed..limit).find(|&v| reachable.contains(v)) {
+            Some(v) => v,
+            None => return Ok(vec![best_single]),
+        };
+
+        // walk back through the choices, taking a directory before any of its subdirectories
+        let mut to_delete = Vec::new();
+        let mut idx = 0;
+        while remaining > 0 {
+            match takeable[idx] {
+                Some(total) if total <= remaining && reachable_from[remaining - total] >= subtree_end[idx] => {
+                    to_delete.push(fs.dir(order[idx]));
+                    remaining -= total;
+                    idx = subtree_end[idx];
+                },
+                _ => idx += 1,
+            }
+        }
+        Ok(to_delete)
+    }
+}
+
+/// The target of a `cd <path>` command, everything after the first space so that names
+/// containing spaces can be entered
+fn cd_target(command:&str) -> Option<&str>
+{
+    command.strip_prefix(CD_CMD)?.strip_prefix(' ').filter(|target| !target.is_empty())
 }
 
+/// Apply the target of a `$ cd <path>` to cwd, the path may be absolute or relative with any
+/// number of components
 fn resolve_path(cwd:&mut PathBuf, op:&str)
 {
-    if let Some(cd_path) = op.split_ascii_whitespace().nth(2) {
-        if cd_path == CD_PARENT {
-            cwd.pop();
-        } else {
-            cwd.push(cd_path);
+    if let Some(cd_path) = op.strip_prefix(CMD_PREFIX).and_then(cd_target) {
+        for component in Path::new(cd_path).components() {
+            match component {
+                Component::RootDir => *cwd = PathBuf::from(ROOT_PATH),
+                Component::ParentDir => {
+                    cwd.pop();
+                },
+                Component::Normal(name) => cwd.push(name),
+                Component::CurDir | Component::Prefix(_) => {},
+            }
         }
     }
 }
 
-fn day7(file_path:&str) -> Option<(usize, usize)>
+/// Interpret a shell transcript of `$ cd` and `$ ls` commands and their output into a
+/// filesystem tree, stopping at the first line that does not make sense
+fn parse<R: Read>(bf: BufReader<R>) -> Result<Day7Fs, Day7Err>
 {
-    let bf = BufReader::new(File::open(file_path).expect(file_path));
-
     let mut current_path = PathBuf::from(ROOT_PATH);
-    let mut root = Day7Dir::new(&current_path);
-
-    for line in bf.lines() {
-        let entry = line.ok()?;
-        if entry.starts_with(&CD_CMD) {
-            resolve_path(&mut current_path, &entry);
-        } else if entry.starts_with(&DIR_PREFIX) {
-            let parts:Vec<&str> = entry.split(" ").collect();
-            let subdir = current_path.join(parts[1]);
-            if let Some(that_dir) = find_dir(&mut root, &current_path) {
-                that_dir.directories.insert(subdir.clone(), Day7Dir::new(&subdir));
+    let mut root = Day7Fs::new();
+    let mut cwd = Day7Fs::ROOT;
+    // set while reading the output of an ls
+    let mut listing = false;
+
+    for (idx, line) in bf.lines().enumerate() {
+        let entry = line.map_err(|_| Day7Err::Io)?;
+        let line = idx + 1;
+        if let Some(command) = entry.strip_prefix(CMD_PREFIX) {
+            let args:Vec<&str> = command.split_ascii_whitespace().collect();
+            listing = false;
+            if cd_target(command).is_some() {
+                resolve_path(&mut current_path, &entry);
+                cwd = root.find_dir(&current_path).ok_or_else(|| Day7Err::UnknownDirectory { line, path: current_path.clone() })?;
+            } else if args == [LS_CMD] {
+                listing = true;
+            } else {
+                return Err(Day7Err::UnknownCommand { line, command: command.to_string() });
             }
-
-        } else if entry.starts_with(&LS_CMD) {
             continue;
+        }
+        if !listing {
+            return Err(Day7Err::UnexpectedOutput { line });
+        }
+
+        let (kind, name) = entry.split_once(' ').ok_or(Day7Err::InvalidEntry { line })?;
+        if name.is_empty() || name.contains('/') || name == "." || name == ".." {
+            return Err(Day7Err::InvalidEntry { line });
+        }
+        let path = current_path.join(name);
+        if entry.starts_with(DIR_PREFIX) {
+            if root.file_size(&path).is_some() {
+                return Err(Day7Err::ConflictingEntry { line, path });
+            }
+            root.add_dir(cwd, name);
         } else {
-            match entry.split_once(" ") {
-                Some((file_size, file_name)) => {
-                    if let Ok(file_size_value) = file_size.parse::<usize>() {
-                        if let Some(that_dir) = find_dir(&mut root, &current_path) {
-                            let file_path = current_path.join(file_name);
-                            //println!("Inserting file {:?} into {:?}", &file_path, &that_dir);
-                            that_dir.files.insert(file_path, file_size_value);
-                        }
-                    }
-                },
-                None => {},
+            let file_size_value = kind.parse::<usize>().map_err(|_| Day7Err::InvalidEntry { line })?;
+            if root.find_dir(&path).is_some() || root.file_size(&path).is_some_and(|size| size != file_size_value) {
+                return Err(Day7Err::ConflictingEntry { line, path });
             }
+            root.add_file(cwd, name, file_size_value);
         }
     }
+    Ok(root)
+}
 
-    let mut big_ones:Vec<&Day7Dir> = Vec::new();
-    find_big_ones(&root, &mut big_ones);
-    let total_size_of_big_ones = big_ones.iter().map(|&d| d.total()).sum::<usize>();
+fn day7(file_path:&str, planner:&DeletionPlanner, as_set:bool) -> Result<(usize, usize), Day7Err>
+{
+    let root = parse(BufReader::new(File::open(file_path).map_err(|_| Day7Err::Io)?))?;
+    let root_total = root.dir(Day7Fs::ROOT).total();
+
+    let small_ones = planner.small_dirs(&root);
+    let total_size_of_small_ones = small_ones.iter().map(|&d| d.total()).sum::<usize>();
 
-    println!("root size is {}", root.total());
-    println!("unused space {}", 70000000 - root.total());
-    let space_required = 30000000 - (70000000 - root.total());
+    println!("root size is {}", root_total);
+    println!("unused space {}", planner.capacity.saturating_sub(root_total));
+    let space_required = planner.space_needed(&root);
     println!("need additional space freed up {}", space_required);
 
-    let mut big_ones:Vec<&Day7Dir> = Vec::new();
-    find_delete_candidates(&root, &mut big_ones, space_required);
-    big_ones.sort_by_key(|d| d.total());
-    for f in &big_ones {
-        println!("candidate {:?} {} (target {})", f.path, f.total(), space_required);
+    let to_delete = if as_set {
+        planner.smallest_set(&root)?
+    } else {
+        for f in planner.delete_candidates(&root) {
+            println!("candidate {:?} {} (target {})", f.path, f.total(), space_required);
+        }
+        planner.smallest_single(&root)?.into_iter().collect()
+    };
+    for f in &to_delete {
+        println!("delete {:?} {}", f.path, f.total());
     }
-    // dbg!(big_ones.iter().filter(|&d| d.total() >= 30000000).collect::<Vec<&Day7Dir>>());
 
-    Some((total_size_of_big_ones, big_ones[0].total()))
+    Ok((total_size_of_small_ones, to_delete.iter().map(|d| d.total()).sum()))
 }
 
 fn main()
 {
     let args:Vec<String> = env::args().collect();
-    if let None = args.get(1) {
-        println!("Usage: {} <path>", args[0]);
+    let usage = || {
+        println!("Usage: {} <path> [--capacity <size>] [--required <size>] [--threshold <size>] [--set] [--tree | --du | --json | --transcript]", args[0]);
         std::process::exit(1);
+    };
+    if args.get(1).is_none() {
+        usage();
     }
-    println!("{:?}", day7(&args[1]));
+    let mut planner = DeletionPlanner::default();
+    let mut as_set = false;
+    let mut render:Option<fn(&Day7Fs) -> String> = None;
+    let mut options = args.iter().skip(2);
+    while let Some(option) = options.next() {
+        let setting = match option.as_str() {
+            "--set" => {
+                as_set = true;
+                continue;
+            },
+            "--tree" | "--du" | "--json" | "--transcript" => {
+                render = Some(match option.as_str() {
+                    "--tree" => Day7Fs::render_tree,
+                    "--du" => Day7Fs::render_du,
+                    "--json" => Day7Fs::render_json,
+                    _ => Day7Fs::render_transcript,
+                });
+                continue;
+            },
+            "--capacity" => &mut planner.capacity,
+            "--required" => &mut planner.required,
+            "--threshold" => &mut planner.small_threshold,
+            _ => return usage(),
+        };
+        match options.next().and_then(|v| v.parse().ok()) {
+            Some(v) => *setting = v,
+            None => usage(),
+        }
+    }
+    if let Some(render) = render {
+        match parse(BufReader::new(File::open(&args[1]).expect(&args[1]))) {
+            Ok(root) => print!("{}", render(&root)),
+            Err(e) => println!("{:?}", e),
+        }
+        return;
+    }
+    println!("{:?}", day7(&args[1], &planner, as_set));
 }
 
 
 #[cfg(test)]
 mod tests {
-    use super::{ROOT_PATH, resolve_path};
+    use super::{ROOT_PATH, Day7Err, Day7Fs, DeletionPlanner, json_escape, parse, resolve_path};
+    use std::io::BufReader;
     use std::path::PathBuf;
 
+    static DATA:&str = concat!(
+        "$ cd /\n", "$ ls\n", "dir a\n", "14848514 b.txt\n", "8504156 c.dat\n", "dir d\n",
+        "$ cd a\n", "$ ls\n", "dir e\n", "29116 f\n", "2557 g\n", "62596 h.lst\n",
+        "$ cd e\n", "$ ls\n", "584 i\n",
+        "$ cd ..\n", "$ cd ..\n", "$ cd d\n", "$ ls\n",
+        "4060174 j\n", "8033020 d.log\n", "5626152 d.ext\n", "7214296 k\n",
+    );
+
     #[test]
     fn test_resolve_path() {
         let mut p = PathBuf::from(ROOT_PATH);
@@ -249,5 +647,130 @@ mod tests {
 
         resolve_path(&mut p, "$ cd /");
         assert_eq!(p.to_str(), Some("/"));
+
+        resolve_path(&mut p, "$ cd a/b/c");
+        assert_eq!(p.to_str(), Some("/a/b/c"));
+
+        resolve_path(&mut p, "$ cd ../../d/./e");
+        assert_eq!(p.to_str(), Some("/a/d/e"));
+
+        resolve_path(&mut p, "$ cd /x/y/");
+        assert_eq!(p.to_str(), Some("/x/y"));
+
+        resolve_path(&mut p, "$ cd ../../../..");
+        assert_eq!(p.to_str(), Some("/"));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let parse_str = |s:&str| parse(BufReader::new(s.as_bytes()));
+        assert_eq!(parse_str("$ cd /\n$ ls\ndir a\n$ pwd\n").unwrap_err(), Day7Err::UnknownCommand { line: 4, command: "pwd".to_string() });
+        assert_eq!(parse_str("$ cd\n").unwrap_err(), Day7Err::UnknownCommand { line: 1, command: "cd".to_string() });
+        assert_eq!(parse_str("$ ls -l\n").unwrap_err(), Day7Err::UnknownCommand { line: 1, command: "ls -l".to_string() });
+        assert_eq!(parse_str("$ ls\ndir a\n$ cd a/b\n").unwrap_err(), Day7Err::UnknownDirectory { line: 3, path: PathBuf::from("/a/b") });
+        assert_eq!(parse_str("dir a\n").unwrap_err(), Day7Err::UnexpectedOutput { line: 1 });
+        assert_eq!(parse_str("$ ls\ndir a\n$ cd a\n12 b\n").unwrap_err(), Day7Err::UnexpectedOutput { line: 4 });
+        assert_eq!(parse_str("$ ls\nbig b\n").unwrap_err(), Day7Err::InvalidEntry { line: 2 });
+        assert_eq!(parse_str("$ ls\n12 a/b\n").unwrap_err(), Day7Err::InvalidEntry { line: 2 });
+        assert_eq!(parse_str("$ ls\nnonsense\n").unwrap_err(), Day7Err::InvalidEntry { line: 2 });
+        assert_eq!(parse_str("$ ls\ndir a\n12 a\n").unwrap_err(), Day7Err::ConflictingEntry { line: 3, path: PathBuf::from("/a") });
+        assert_eq!(parse_str("$ ls\n12 a\n$ ls\ndir a\n").unwrap_err(), Day7Err::ConflictingEntry { line: 4, path: PathBuf::from("/a") });
+        assert_eq!(parse_str("$ ls\n12 a\n$ ls\n13 a\n").unwrap_err(), Day7Err::ConflictingEntry { line: 4, path: PathBuf::from("/a") });
+
+        // multi-segment cd into listed directories
+        let root = parse_str(&(DATA.to_string() + "$ cd /a/e\n$ ls\n1 z\n$ cd ../../d\n$ ls\n2 z\n")).unwrap();
+        assert_eq!(root.file_size(&PathBuf::from("/a/e/z")), Some(1));
+        assert_eq!(root.file_size(&PathBuf::from("/d/z")), Some(2));
+    }
+
+    #[test]
+    fn test_day7_fs() {
+        let root = parse(BufReader::new(DATA.as_bytes())).unwrap();
+        let paths:Vec<(&str, usize)> = root.iter().map(|d| (d.path.to_str().unwrap(), d.total())).collect();
+        assert_eq!(paths, vec![("/", 48381165), ("/a", 94853), ("/a/e", 584), ("/d", 24933642)]);
+        assert_eq!(DeletionPlanner::default().small_dirs(&root).iter().map(|d| d.total()).sum::<usize>(), 95437);
+
+        // relisting a directory does not count it twice
+        let relisted = DATA.to_string() + "$ ls\n4060174 j\n$ cd /a/e\n$ ls\n584 i\n";
+        let root = parse(BufReader::new(relisted.as_bytes())).unwrap();
+        assert_eq!(root.dir(Day7Fs::ROOT).total(), 48381165);
+        assert_eq!(root.dir(root.find_dir(&PathBuf::from("/a")).unwrap()).total(), 94853);
+        assert_eq!(root.dir(root.find_dir(&PathBuf::from("/a/e")).unwrap()).parent, root.find_dir(&PathBuf::from("/a")));
+    }
+
+    #[test]
+    fn test_deletion_planner() {
+        let root = parse(BufReader::new(DATA.as_bytes())).unwrap();
+        let paths = |dirs:Vec<&super::Day7Dir>| dirs.iter().map(|d| d.path.to_str().unwrap().to_string()).collect::<Vec<String>>();
+
+        let planner = DeletionPlanner::default();
+        assert_eq!(planner.space_needed(&root), 8381165);
+        assert_eq!(planner.smallest_single(&root).unwrap().map(|d| d.total()), Some(24933642));
+        assert_eq!(paths(planner.smallest_set(&root).unwrap()), vec!["/d"]);
+
+        // /a and /d together are smaller than the root
+        let planner = DeletionPlanner { capacity: 48381165, required: 24933642 + 94853, small_threshold: 0 };
+        assert_eq!(planner.smallest_single(&root).unwrap().map(|d| d.total()), Some(48381165));
+        assert_eq!(paths(planner.smallest_set(&root).unwrap()), vec!["/a", "/d"]);
+        // /a/e is enough in place of /a
+        let planner = DeletionPlanner { required: 24933642 + 584, ..planner };
+        assert_eq!(paths(planner.smallest_set(&root).unwrap()), vec!["/a/e", "/d"]);
+        assert!(planner.small_dirs(&root).is_empty());
+
+        // plenty of room already
+        let planner = DeletionPlanner { capacity: 100000000, ..DeletionPlanner::default() };
+        assert!(planner.smallest_single(&root).unwrap().is_none());
+        assert!(planner.smallest_set(&root).unwrap().is_empty());
+
+        // more than the disk can ever hold
+        let planner = DeletionPlanner { required: 70000000 + 1, ..DeletionPlanner::default() };
+        let unreachable = Day7Err::CannotFreeEnough { needed: 48381166, available: 48381165 };
+        assert_eq!(planner.smallest_single(&root).unwrap_err(), unreachable);
+        assert_eq!(planner.smallest_set(&root).unwrap_err(), unreachable);
+    }
+
+    #[test]
+    fn test_render() {
+        let root = parse(BufReader::new(DATA.as_bytes())).unwrap();
+        assert_eq!(root.render_tree(), concat!(
+            "- / (dir)\n",
+            "  - a (dir)\n",
+            "    - e (dir)\n",
+            "      - i (file, size=584)\n",
+            "    - f (file, size=29116)\n",
+            "    - g (file, size=2557)\n",
+            "    - h.lst (file, size=62596)\n",
+            "  - b.txt (file, size=14848514)\n",
+            "  - c.dat (file, size=8504156)\n",
+            "  - d (dir)\n",
+            "    - j (file, size=4060174)\n",
+            "    - d.log (file, size=8033020)\n",
+            "    - d.ext (file, size=5626152)\n",
+            "    - k (file, size=7214296)\n",
+        ));
+        assert_eq!(root.render_du(), "48381165\t/\n24933642\t/d\n94853\t/a\n584\t/a/e\n");
+        assert_eq!(parse(BufReader::new("$ cd /\n$ ls\ndir x\n1 \"q\"\n".as_bytes())).unwrap().render_json(),
+            r#"{"name":"/","type":"dir","size":1,"entries":[{"name":"x","type":"dir","size":0,"entries":[]},{"name":"\"q\"","type":"file","size":1}]}"#.to_string() + "\n");
+        // every control character is escaped, not just the common ones
+        let controls:String = (0u8..0x20).map(|b| b as char).collect();
+        let expected:String = (0..0x20).map(|b| format!("\\u{:04x}", b)).collect();
+        assert_eq!(json_escape(&controls), expected);
+        assert_eq!(json_escape("a\u{7f}é"), "a\u{7f}é");
+
+        // the transcript parses back into the same tree
+        let transcript = root.render_transcript();
+        assert!(transcript.starts_with("$ cd /\n$ ls\ndir a\n14848514 b.txt\n8504156 c.dat\ndir d\n$ cd a\n$ ls\ndir e\n"));
+        let reparsed = parse(BufReader::new(transcript.as_bytes())).unwrap();
+        assert_eq!(reparsed.render_tree(), root.render_tree());
+        assert_eq!(reparsed.render_json(), root.render_json());
+        assert_eq!(reparsed.render_transcript(), transcript);
+
+        // names with spaces survive the round trip, including as cd targets
+        let spaced = parse(BufReader::new("$ cd /\n$ ls\ndir a b\n$ cd a b\n$ ls\n123 x y.txt\n".as_bytes())).unwrap();
+        assert_eq!(spaced.render_tree(), "- / (dir)\n  - a b (dir)\n    - x y.txt (file, size=123)\n");
+        let transcript = spaced.render_transcript();
+        let reparsed = parse(BufReader::new(transcript.as_bytes())).unwrap();
+        assert_eq!(reparsed.render_tree(), spaced.render_tree());
+        assert_eq!(reparsed.render_transcript(), transcript);
     }
 }
@@ -21,6 +21,129 @@ use std::fs::File;
 use std::io::prelude::*;
 use std::io::BufReader;
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Direction {
+    Top,
+    Bottom,
+    Left,
+    Right,
+}
+impl Direction {
+    const ALL:[Direction; 4] = [Direction::Top, Direction::Bottom, Direction::Left, Direction::Right];
+}
+
+/// Visibility from each edge and viewing distance towards each edge for every tree, indexed
+/// like `TreeGrid::data` and then by `Direction`
+#[derive(Debug, PartialEq, Eq)]
+struct Sightlines {
+    columns: usize,
+    visible: Vec<[bool; 4]>,
+    distances: Vec<[usize; 4]>,
+}
+impl Sightlines {
+    fn scenic_score(&self, idx:usize) -> usize {
+        self.distances[idx].iter().product()
+    }
+
+    /// what the tree at row, column can see, None if outside the grid
+    fn tree(&self, row:usize, column:usize) -> Option<TreeView> {
+        if column >= self.columns {
+            return None;
+        }
+        let idx = row * self.columns + column;
+        let visible = self.visible.get(idx)?;
+        Some(TreeView {
+            visible_from: Direction::ALL.iter().copied().filter(|&d| visible[d as usize]).collect(),
+            distances: self.distances[idx],
+            scenic_score: self.scenic_score(idx),
+        })
+    }
+
+    fn heatmap(&self, kind:HeatmapKind) -> Heatmap {
+        let values = (0..self.visible.len()).map(|idx| match kind {
+            HeatmapKind::Visibility => self.visible[idx].iter().filter(|&&v| v).count(),
+            HeatmapKind::Scenic => self.scenic_score(idx),
+        }).collect::<Vec<usize>>();
+        let max = values.iter().copied().max().unwrap_or(0);
+        Heatmap { columns: self.columns, values, max }
+    }
+}
+
+/// Query result for a single tree
+#[derive(Debug, PartialEq, Eq)]
+struct TreeView {
+    /// the edges the tree can be seen from
+    visible_from: Vec<Direction>,
+    /// viewing distance towards each edge, indexed by `Direction`
+    distances: [usize; 4],
+    scenic_score: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum HeatmapKind {
+    /// how many edges each tree can be seen from
+    Visibility,
+    Scenic,
+}
+
+/// A value per tree scaled against the largest value for rendering
+#[derive(Debug)]
+struct Heatmap {
+    columns: usize,
+    values: Vec<usize>,
+    max: usize,
+}
+impl Heatmap {
+    /// value scaled to 0..=255
+    fn level(&self, idx:usize) -> u8 {
+        (self.values[idx] * 255 / self.max.max(1)) as u8
+    }
+
+    /// blue through green and yellow to red
+    fn color(level:u8) -> (u8, u8, u8) {
+        let l = level as usize;
+        match l {
+            0..=84 => (0, (l * 3) as u8, (255 - l * 3) as u8),
+            85..=169 => (((l - 85) * 3) as u8, 255, 0),
+            _ => (255, (255 - (l - 170) * 3) as u8, 0),
+        }
+    }
+
+    fn rows(&self) -> usize {
+        self.values.len().checked_div(self.columns).unwrap_or(0)
+    }
+
+    /// two terminal cells per tree with a 24-bit background colour
+    fn to_ansi(&self) -> String {
+        let mut out = String::new();
+        for (idx, _) in self.values.iter().enumerate() {
+            let (r, g, b) = Self::color(self.level(idx));
+            out.push_str(&format!("\x1b[48;2;{};{};{}m  ", r, g, b));
+            if (idx + 1) % self.columns == 0 {
+                out.push_str("\x1b[0m\n");
+            }
+        }
+        out
+    }
+
+    /// binary greyscale PGM
+    fn to_pgm(&self) -> Vec<u8> {
+        let mut out = format!("P5\n{} {}\n255\n", self.columns, self.rows()).into_bytes();
+        out.extend((0..self.values.len()).map(|idx| self.level(idx)));
+        out
+    }
+
+    /// binary colour PPM using the same palette as the terminal
+    fn to_ppm(&self) -> Vec<u8> {
+        let mut out = format!("P6\n{} {}\n255\n", self.columns, self.rows()).into_bytes();
+        for idx in 0..self.values.len() {
+            let (r, g, b) = Self::color(self.level(idx));
+            out.extend([r, g, b]);
+        }
+        out
+    }
+}
+
 #[derive(Debug)]
 struct TreeGrid {
     columns: usize,
@@ -28,116 +151,225 @@ struct TreeGrid {
     data: Vec<usize>,
 }
 impl TreeGrid {
+    /// Line of sight for every tree in O(rows*columns). Each row and column is scanned from
+    /// each edge keeping the tallest tree so far for visibility, and a stack of strictly
+    /// decreasing heights so the nearest blocking tree is on top for the viewing distance.
+    fn sightlines(&self) -> Sightlines {
+        let mut sightlines = Sightlines { columns: self.columns, visible: vec![[false; 4]; self.data.len()], distances: vec![[0; 4]; self.data.len()] };
+        let rows = self.rows;
+        let mut stack:Vec<(usize, usize)> = Vec::new();
+        for direction in Direction::ALL {
+            let (lines, line_len) = match direction {
+                Direction::Top | Direction::Bottom => (self.columns, rows),
+                Direction::Left | Direction::Right => (rows, self.columns),
+            };
+            for line in 0..lines {
+                stack.clear();
+                let mut tallest:Option<usize> = None;
+                for pos in 0..line_len {
+                    // pos counts from the edge we are looking towards
+                    let idx = match direction {
+                        Direction::Top => pos * self.columns + line,
+                        Direction::Bottom => (rows - 1 - pos) * self.columns + line,
+                        Direction::Left => line * self.columns + pos,
+                        Direction::Right => line * self.columns + self.columns - 1 - pos,
+                    };
+                    let value = self.data[idx];
+                    sightlines.visible[idx][direction as usize] = tallest.is_none_or(|t| value > t);
+                    tallest = tallest.max(Some(value));
+
+                    while stack.last().is_some_and(|&(_, h)| h < value) {
+                        stack.pop();
+                    }
+                    sightlines.distances[idx][direction as usize] = match stack.last() {
+                        Some(&(blocker, _)) => pos - blocker,
+                        None => pos,
+                    };
+                    stack.push((pos, value));
+                }
+            }
+        }
+        sightlines
+    }
+
+    fn find_best_scenic_score(&self) -> usize {
+        let sightlines = self.sightlines();
+        (0..self.data.len()).map(|idx| sightlines.scenic_score(idx)).max().unwrap_or(0)
+    }
+
+    fn count_visible(&self) -> usize {
+        self.sightlines().visible.iter().filter(|v| v.iter().any(|&v| v)).count()
+    }
+}
+
+// single tree checks walking out to each edge, the reference sightlines is tested against
+#[cfg(test)]
+impl TreeGrid {
+    /// the next index from idx towards the edge in the given direction
+    fn step(&self, idx:usize, direction:Direction) -> Option<usize> {
+        match direction {
+            Direction::Top => idx.checked_sub(self.columns),
+            Direction::Bottom => Some(idx + self.columns).filter(|&i| i < self.data.len()),
+            Direction::Left => Some(idx).filter(|i| i % self.columns != 0).map(|i| i - 1),
+            Direction::Right => Some(idx + 1).filter(|i| i % self.columns != 0),
+        }
+    }
+
+    /// walk from idx to the edge, returning the viewing distance for a tree of the given height
+    /// and whether it can be seen from that edge
+    fn look(&self, idx:usize, value:usize, direction:Direction) -> (usize, bool) {
+        let mut distance = 0;
+        let mut next = self.step(idx, direction);
+        while let Some(i) = next {
+            distance += 1;
+            if self.data[i] >= value {
+                return (distance, false);
+            }
+            next = self.step(i, direction);
+        }
+        (distance, true)
+    }
+
     fn get_row_for_idx(&self, idx:usize) -> Vec<&usize> {
         let row_start:usize = idx - idx % self.columns;
-        self.data.as_slice()[row_start..row_start+self.columns].into_iter().collect()
+        self.data.as_slice()[row_start..row_start+self.columns].iter().collect()
     }
 
     fn get_column_for_idx(&self, idx:usize) -> Vec<&usize> {
         let col_idx = idx % self.columns;
-        self.data.as_slice()[col_idx..].into_iter().step_by(self.columns).collect()
+        self.data.as_slice()[col_idx..].iter().step_by(self.columns).collect()
     }
 
     fn is_idx_visible_for_row_or_column(&self, idx:usize, value:usize) -> bool {
-        let idx_for_row = idx % self.columns;
-        let idx_for_col = idx / self.columns;
-        let row = self.get_row_for_idx(idx);
-        let column = self.get_column_for_idx(idx);
-
-        let visible_from_top = column.as_slice()[..idx_for_col].into_iter().any(|&&i| i >= value);
-        let visible_from_bottom = column.as_slice()[idx_for_col+1..].into_iter().any(|&&i| i >= value);
-        let visible_from_left = row.as_slice()[..idx_for_row].into_iter().any(|&&i| i >= value);
-        let visible_from_right = row.as_slice()[idx_for_row+1..].into_iter().any(|&&i| i >= value);
-        visible_from_top || visible_from_bottom || visible_from_left || visible_from_right
+        self.is_idx_edge(idx) || Direction::ALL.iter().any(|&d| self.look(idx, value, d).1)
     }
 
     fn is_idx_edge(&self, idx:usize) -> bool {
-        (idx % self.columns) == 0 || (idx + 1) % self.columns == 0
+        idx.is_multiple_of(self.columns) || (idx + 1).is_multiple_of(self.columns) || idx < self.columns || idx >= self.data.len() - self.columns
     }
 
     fn idx_scenic_score(&self, idx:usize, value:usize) -> usize {
-        let idx_for_row = idx % self.columns;
-        let idx_for_col = idx / self.columns;
-        let row = self.get_row_for_idx(idx);
-        let column = self.get_column_for_idx(idx);
-
-        let mut top_score = 0;
-        for &e in column.as_slice()[..idx_for_col].into_iter().rev() { // revsered
-            top_score += 1;
-            if *e >= value { break; }
-        }
-        let mut bottom_score = 0;
-        for &e in column.as_slice()[idx_for_col+1..self.columns].into_iter() {
-            bottom_score += 1;
-            if *e >= value { break; }
-        }
-        let mut left_score = 0;
-        for &e in row.as_slice()[..idx_for_row].into_iter().rev() { // reversed
-            left_score += 1;
-            if *e >= value { break; }
-        }
-        let mut right_score = 0;
-        for &e in row.as_slice()[idx_for_row+1..self.rows].into_iter() {
-            right_score += 1;
-            if *e >= value { break; }
-        }
-        top_score * bottom_score * left_score * right_score
-    }
-
-    fn find_best_scenic_score(&self) -> usize {
-        self.data.iter().enumerate().map(|(idx, value)| self.idx_scenic_score(idx, *value) ).max().unwrap_or(0)
+        Direction::ALL.iter().map(|&d| self.look(idx, value, d).0).product()
     }
+}
 
-    fn count_visible(&self) -> usize {
-        let mut visible = 0;
-        let last_row_idx = (self.columns * self.rows) - self.columns - 1;
-        for (idx, value) in self.data.iter().enumerate() {
-            // if we on the top or bottom row, or are edge, or is visible from a direction
-            if idx < self.rows || idx >= last_row_idx || self.is_idx_edge(idx) || self.is_idx_visible_for_row_or_column(idx, *value) {
-                visible += 1;
-            }
-        }
-        visible
-    }
+#[derive(Debug, PartialEq, Eq)]
+enum TreeGridErr {
+    Io,
+    Empty,
+    /// a row that is not as wide as the first one
+    RaggedRow { line: usize, expected: usize, found: usize },
+    /// a cell that is not a single digit height, column is 1 based
+    InvalidHeight { line: usize, column: usize, found: char },
 }
 
-fn parse_file(file_path:&str) -> Option<TreeGrid>
+/// Load a grid of digit heights, every row must be as wide as the first. Leading and trailing
+/// blank lines and CRLF line endings are accepted.
+fn parse<R: Read>(bf: BufReader<R>) -> Result<TreeGrid, TreeGridErr>
 {
-    let bf = BufReader::new(File::open(file_path).expect(file_path));
     let mut tree_grid = TreeGrid { columns: 0, rows: 0, data: Vec::new() };
+    let mut blank_line:Option<usize> = None;
 
-    for line in bf.lines() {
-        let grid_line = line.ok()?;
-        tree_grid.columns = grid_line.len();
-        tree_grid.rows += 1;
-        for val in grid_line.as_str().chars().map(|c| c.to_digit(10).unwrap_or(0) as usize) {
-            tree_grid.data.push(val);
+    for (idx, line) in bf.lines().enumerate() {
+        let grid_line = line.map_err(|_| TreeGridErr::Io)?;
+        let grid_line = grid_line.strip_suffix('\r').unwrap_or(&grid_line);
+        let line = idx + 1;
+        if grid_line.is_empty() {
+            // only a blank line after the first row can split the grid
+            if tree_grid.rows > 0 {
+                blank_line.get_or_insert(line);
+            }
+            continue;
         }
+        // a blank line with more rows after it
+        if let Some(blank) = blank_line {
+            return Err(TreeGridErr::RaggedRow { line: blank, expected: tree_grid.columns, found: 0 });
+        }
+
+        let row_start = tree_grid.data.len();
+        for (column, c) in grid_line.chars().enumerate() {
+            match c.to_digit(10) {
+                Some(val) => tree_grid.data.push(val as usize),
+                None => return Err(TreeGridErr::InvalidHeight { line, column: column + 1, found: c }),
+            }
+        }
+        let width = tree_grid.data.len() - row_start;
+        if tree_grid.rows == 0 {
+            tree_grid.columns = width;
+        } else if width != tree_grid.columns {
+            return Err(TreeGridErr::RaggedRow { line, expected: tree_grid.columns, found: width });
+        }
+        tree_grid.rows += 1;
     }
-    Some(tree_grid)
+    if tree_grid.rows == 0 {
+        return Err(TreeGridErr::Empty);
+    }
+    Ok(tree_grid)
+}
+
+fn parse_file(file_path:&str) -> Result<TreeGrid, TreeGridErr>
+{
+    parse(BufReader::new(File::open(file_path).map_err(|_| TreeGridErr::Io)?))
 }
 
 fn main()
 {
     let args:Vec<String> = env::args().collect();
-    if let None = args.get(1) {
-        println!("Usage: {} <path>", args[0]);
+    let usage = || {
+        println!("Usage: {} <path> [--tree <row> <column> | --heatmap visibility|scenic [<path.pgm|path.ppm>]]", args[0]);
         std::process::exit(1);
+    };
+    if args.get(1).is_none() {
+        usage();
     }
 
-    let tree_grid = parse_file(&args[1]).unwrap();
+    let tree_grid = match parse_file(&args[1]) {
+        Ok(tree_grid) => tree_grid,
+        Err(e) => {
+            println!("{:?}", e);
+            std::process::exit(1);
+        },
+    };
+    match args.get(2).map(|a| a.as_str()) {
+        Some("--tree") => {
+            let (row, column) = match (args.get(3).and_then(|v| v.parse().ok()), args.get(4).and_then(|v| v.parse().ok())) {
+                (Some(row), Some(column)) => (row, column),
+                _ => return usage(),
+            };
+            println!("{:?}", tree_grid.sightlines().tree(row, column));
+            return;
+        },
+        Some("--heatmap") => {
+            let kind = match args.get(3).map(|a| a.as_str()) {
+                Some("visibility") => HeatmapKind::Visibility,
+                Some("scenic") => HeatmapKind::Scenic,
+                _ => return usage(),
+            };
+            let heatmap = tree_grid.sightlines().heatmap(kind);
+            match args.get(4) {
+                Some(path) if path.ends_with(".pgm") => std::fs::write(path, heatmap.to_pgm()).expect(path),
+                Some(path) if path.ends_with(".ppm") => std::fs::write(path, heatmap.to_ppm()).expect(path),
+                Some(_) => usage(),
+                None => print!("{}", heatmap.to_ansi()),
+            }
+            return;
+        },
+        Some(_) => usage(),
+        None => {},
+    }
     dbg!(&tree_grid.count_visible());
     dbg!(&tree_grid.find_best_scenic_score());
 }
 
 #[cfg(test)]
 mod tests {
-    use super::TreeGrid;
+    use super::{parse, Direction, HeatmapKind, TreeGrid, TreeGridErr, TreeView};
+    use std::io::BufReader;
 
     #[test]
     fn test_tree_grid() {
         let g = TreeGrid{columns: 5, rows: 5, data: vec![3,0,3,7,3, 2,5,5,1,2, 6,5,3,3,2, 3,3,5,4,9, 3,5,3,9,0]};
-        assert_eq!(g.count_visible(), 25);
+        assert_eq!(g.count_visible(), 21);
         assert!(g.is_idx_edge(0));
         assert!(g.is_idx_edge(4));
         assert!(!g.is_idx_edge(6));
@@ -147,5 +379,90 @@ mod tests {
         assert_eq!(g.idx_scenic_score(7, 5), 4);
         assert_eq!(g.idx_scenic_score(17, 5), 8);
         assert_eq!(g.find_best_scenic_score(), 8);
+        assert!(!g.is_idx_visible_for_row_or_column(12, 3));
+
+        // every tree agrees with walking out to the edges
+        let sightlines = g.sightlines();
+        for (idx, &value) in g.data.iter().enumerate() {
+            assert_eq!(sightlines.visible[idx].iter().any(|&v| v), g.is_idx_visible_for_row_or_column(idx, value), "visible {}", idx);
+            assert_eq!(sightlines.scenic_score(idx), g.idx_scenic_score(idx, value), "scenic score {}", idx);
+        }
+    }
+
+    #[test]
+    fn test_tree_grid_rectangular() {
+        // 3 rows of 6
+        let g = TreeGrid{columns: 6, rows: 3, data: vec![1,2,3,4,5,6, 6,1,9,1,1,2, 0,3,2,8,7,1]};
+        let sightlines = g.sightlines();
+        for (idx, &value) in g.data.iter().enumerate() {
+            for d in Direction::ALL {
+                let (distance, visible) = g.look(idx, value, d);
+                assert_eq!(sightlines.distances[idx][d as usize], distance, "distance {} {:?}", idx, d);
+                assert_eq!(sightlines.visible[idx][d as usize], visible, "visible {} {:?}", idx, d);
+            }
+        }
+        // only the 1s in the middle row are hidden
+        assert_eq!(g.count_visible(), 15);
+        assert_eq!(sightlines.distances[8], [1, 1, 2, 3]);
+        assert_eq!(g.idx_scenic_score(8, 9), 6);
+        assert_eq!(g.find_best_scenic_score(), 6);
+        assert_eq!(g.get_column_for_idx(8), vec![&3, &9, &2]);
+
+        // a single column
+        let g = TreeGrid{columns: 1, rows: 4, data: vec![3,1,2,5]};
+        assert_eq!(g.count_visible(), 4);
+        assert_eq!(g.sightlines().distances[2], [2, 1, 0, 0]);
+    }
+
+    #[test]
+    fn test_tree_queries() {
+        let g = TreeGrid{columns: 5, rows: 5, data: vec![3,0,3,7,3, 2,5,5,1,2, 6,5,3,3,2, 3,3,5,4,9, 3,5,3,9,0]};
+        let sightlines = g.sightlines();
+        assert_eq!(sightlines.tree(1, 2), Some(TreeView{visible_from: vec![Direction::Top, Direction::Right], distances: [1, 2, 1, 2], scenic_score: 4}));
+        assert_eq!(sightlines.tree(3, 2), Some(TreeView{visible_from: vec![Direction::Bottom, Direction::Left], distances: [2, 1, 2, 2], scenic_score: 8}));
+        assert_eq!(sightlines.tree(2, 2).unwrap().visible_from, vec![]);
+        assert_eq!(sightlines.tree(0, 5), None);
+        assert_eq!(sightlines.tree(5, 0), None);
+
+        let heatmap = sightlines.heatmap(HeatmapKind::Scenic);
+        assert_eq!(heatmap.level(17), 255);
+        assert_eq!(heatmap.level(0), 0);
+        let pgm = heatmap.to_pgm();
+        assert!(pgm.starts_with(b"P5\n5 5\n255\n"));
+        assert_eq!(pgm.len(), b"P5\n5 5\n255\n".len() + 25);
+        assert_eq!(pgm[pgm.len() - 25 + 7], 127);
+        let ppm = sightlines.heatmap(HeatmapKind::Visibility).to_ppm();
+        assert!(ppm.starts_with(b"P6\n5 5\n255\n"));
+        assert_eq!(ppm.len(), b"P6\n5 5\n255\n".len() + 75);
+        // the middle tree is hidden from every edge, corners are seen from two of the four
+        assert_eq!(&ppm[ppm.len() - 75 + 36..ppm.len() - 75 + 39], &[0, 0, 255]);
+        assert_eq!(&ppm[ppm.len() - 75..ppm.len() - 72], &[126, 255, 0]);
+
+        let ansi = sightlines.heatmap(HeatmapKind::Visibility).to_ansi();
+        assert_eq!(ansi.lines().count(), 5);
+        assert!(ansi.starts_with("\x1b[48;2;126;255;0m  "));
+    }
+
+    #[test]
+    fn test_parse() {
+        let parse_str = |s:&str| parse(BufReader::new(s.as_bytes()));
+        let g = parse_str("30373\n25512\n65332\n33549\n35390\n").unwrap();
+        assert_eq!((g.columns, g.rows, g.count_visible(), g.find_best_scenic_score()), (5, 5, 21, 8));
+
+        let g = parse_str("123456\r\n619112\r\n032871\r\n\r\n\n").unwrap();
+        assert_eq!((g.columns, g.rows, g.count_visible()), (6, 3, 15));
+
+        assert_eq!(parse_str("123\n12\n").unwrap_err(), TreeGridErr::RaggedRow { line: 2, expected: 3, found: 2 });
+        assert_eq!(parse_str("123\n1234\n").unwrap_err(), TreeGridErr::RaggedRow { line: 2, expected: 3, found: 4 });
+        assert_eq!(parse_str("123\n\n123\n").unwrap_err(), TreeGridErr::RaggedRow { line: 2, expected: 3, found: 0 });
+        assert_eq!(parse_str("123\n1x3\n").unwrap_err(), TreeGridErr::InvalidHeight { line: 2, column: 2, found: 'x' });
+        assert_eq!(parse_str("12 \n").unwrap_err(), TreeGridErr::InvalidHeight { line: 1, column: 3, found: ' ' });
+        assert_eq!(parse_str("1é3\n").unwrap_err(), TreeGridErr::InvalidHeight { line: 1, column: 2, found: 'é' });
+        assert_eq!(parse_str("\n\n").unwrap_err(), TreeGridErr::Empty);
+
+        // blank lines before the grid are skipped, line numbers still count them
+        let g = parse_str("\n\r\n123\n456\n").unwrap();
+        assert_eq!((g.columns, g.rows, g.data[3]), (3, 2, 4));
+        assert_eq!(parse_str("\n123\n12\n").unwrap_err(), TreeGridErr::RaggedRow { line: 3, expected: 3, found: 2 });
     }
 }
@@ -34,7 +34,7 @@ impl fmt::Display for Direction {
     }
 }
 
-#[derive(Debug, PartialEq, Eq, Hash, Copy, PartialOrd, Ord)]
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone, PartialOrd, Ord)]
 struct Point {
     x: i32,
     y: i32
@@ -51,57 +51,17 @@ impl Point {
             Direction::Right => self.x += count as i32,
         }
     }
+    /// Chebyshev distance, diagonal neighbours are 1 away
+    fn distance(&self, other:&Point) -> i32 {
+        (other.x - self.x).abs().max((other.y - self.y).abs())
+    }
+    /// Move one step towards other, diagonally if needed, unless already touching
     fn follow(&mut self, other:&Point) {
-
-        // skip equal
-        if self == other {
+        if self.distance(other) <= 1 {
             return;
         }
-
-        // skip adjacent
-        let x_delta = other.x - self.x;
-        let y_delta = other.y - self.y;
-        let distance = f64::sqrt( (x_delta.pow(2) + y_delta.pow(2)) as f64) as u32;
-        if distance == 1 {
-            return; // already adjacent
-        }
-        // check to see if we share the same x or y
-        if x_delta == 0 || y_delta == 0 {
-            let d = match (x_delta, y_delta) {
-                (x, y) if x == 0 && y < 0 => {Direction::Down},
-                (x, y) if x == 0 && y > 0 => {Direction::Up},
-                (x, y) if x < 0 && y == 0 => {Direction::Left},
-                (x, y) if x > 0 && y == 0 => {Direction::Right},
-                (_, _) => todo!("should never happen"),
-            };
-            self.move_position(1, d);
-            return;
-        }
-
-        match (x_delta, y_delta) {
-            (x, y) if x > 0 && y > 0 => {
-                self.move_position(1, Direction::Right);
-                self.move_position(1, Direction::Up);
-            },
-            (x, y) if x > 0 && y < 0 => {
-                self.move_position(1, Direction::Right);
-                self.move_position(1, Direction::Down);
-            },
-            (x, y) if x < 0 && y > 0 => {
-                self.move_position(1, Direction::Left);
-                self.move_position(1, Direction::Up);
-            },
-            (x, y) if x < 0 && y < 0 => {
-                self.move_position(1, Direction::Left);
-                self.move_position(1, Direction::Down);
-            },
-            (_, _) => todo!("should never happen"),
-        }
-    }
-}
-impl Clone for Point {
-    fn clone(&self) -> Self {
-        Self{..*self}
+        self.x += (other.x - self.x).signum();
+        self.y += (other.y - self.y).signum();
     }
 }
 impl Default for Point {
@@ -115,48 +75,393 @@ impl fmt::Display for Point {
     }
 }
 
-fn day7(file_path:&str) -> Option<usize>
+/// One line of the input, a direction and how many steps to move the head
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+struct Motion {
+    direction: Direction,
+    count: u32,
+}
+impl fmt::Display for Motion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let d = match self.direction {
+            Direction::Up => 'U',
+            Direction::Down => 'D',
+            Direction::Left => 'L',
+            Direction::Right => 'R',
+        };
+        write!(f, "{} {}", d, self.count)
+    }
+}
+impl FromStr for Motion {
+    type Err = DirectionParseError;
+    fn from_str(v:&str) -> Result<Self, DirectionParseError> {
+        let (d, c) = v.split_once(' ').ok_or(DirectionParseError)?;
+        let direction = d.parse::<Direction>()?;
+        let count = c.parse::<u32>().map_err(|_| DirectionParseError)?;
+        Ok(Self{direction, count})
+    }
+}
+
+/// Smallest box holding every position a knot has been
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+struct BoundingBox {
+    min: Point,
+    max: Point,
+}
+impl BoundingBox {
+    fn new(p:Point) -> Self {
+        Self{min: p, max: p}
+    }
+    fn extend(&mut self, p:Point) {
+        self.min = Point::new(self.min.x.min(p.x), self.min.y.min(p.y));
+        self.max = Point::new(self.max.x.max(p.x), self.max.y.max(p.y));
+    }
+    fn union(&mut self, other:&BoundingBox) {
+        self.extend(other.min);
+        self.extend(other.max);
+    }
+}
+
+/// Rope of knots where each knot follows the one before it, the first knot being the head
+#[derive(Debug)]
+struct Rope {
+    knots: Vec<Point>,
+    /// positions visited by every knot when tracking all of them, otherwise just the tail
+    visited: Vec<HashSet<Point>>,
+    bounds: Vec<BoundingBox>,
+}
+impl Rope {
+    fn new(length:usize, track_all:bool) -> Self {
+        assert!(length > 0);
+        let start = Point::default();
+        let tracked = if track_all { length } else { 1 };
+        Self {
+            knots: vec![start; length],
+            visited: vec![HashSet::from([start]); tracked],
+            bounds: vec![BoundingBox::new(start); length],
+        }
+    }
+
+    fn tail(&self) -> Point {
+        self.knots[self.knots.len() - 1]
+    }
+
+    /// move the head a single step and let the rest of the rope catch up
+    fn step(&mut self, direction:Direction) {
+        self.knots[0].move_position(1, direction);
+        for idx in 1..self.knots.len() {
+            let leader = self.knots[idx - 1];
+            self.knots[idx].follow(&leader);
+        }
+        let track_all = self.visited.len() > 1;
+        for (idx, knot) in self.knots.iter().enumerate() {
+            self.bounds[idx].extend(*knot);
+            if track_all {
+                self.visited[idx].insert(*knot);
+            }
+        }
+        if !track_all {
+            let tail = self.tail();
+            self.visited[0].insert(tail);
+        }
+    }
+
+    fn apply(&mut self, motion:&Motion) {
+        for _ in 0..motion.count {
+            self.step(motion.direction);
+        }
+    }
+
+    /// how many positions the knot has visited, None if that knot is not tracked
+    fn visits(&self, knot:usize) -> Option<usize> {
+        if self.visited.len() > 1 {
+            self.visited.get(knot).map(|v| v.len())
+        } else if knot + 1 == self.knots.len() {
+            Some(self.visited[0].len())
+        } else {
+            None
+        }
+    }
+
+    fn tail_visits(&self) -> usize {
+        self.visited[self.visited.len() - 1].len()
+    }
+
+    /// label for the knot when drawn, H for the head and T for the tail of a two knot rope
+    fn knot_label(&self, idx:usize) -> char {
+        match idx {
+            0 => 'H',
+            1 if self.knots.len() == 2 => 'T',
+            1..=9 => char::from_digit(idx as u32, 10).unwrap_or('*'),
+            _ => '*',
+        }
+    }
+
+    /// Draw the view like the puzzle examples, y increasing upwards. Knots closer to the head
+    /// are drawn over later ones, then s for the start and # for places the tail has been.
+    fn render(&self, view:&BoundingBox) -> String {
+        let width = (view.max.x - view.min.x + 1) as usize;
+        let height = (view.max.y - view.min.y + 1) as usize;
+        let mut grid = vec![vec!['.'; width]; height];
+        let mut draw = |p:&Point, c:char| {
+            if p.x >= view.min.x && p.x <= view.max.x && p.y >= view.min.y && p.y <= view.max.y {
+                grid[(view.max.y - p.y) as usize][(p.x - view.min.x) as usize] = c;
+            }
+        };
+        for p in &self.visited[self.visited.len() - 1] {
+            draw(p, '#');
+        }
+        draw(&Point::default(), 's');
+        for (idx, knot) in self.knots.iter().enumerate().rev() {
+            draw(knot, self.knot_label(idx));
+        }
+        grid.iter().map(|row| row.iter().collect::<String>() + "\n").collect()
+    }
+
+    /// knot positions as a JSON object, motion is None for the starting frame
+    fn to_json(&self, motion:Option<&Motion>) -> String {
+        let motion = motion.map(|m| format!("\"{}\"", m)).unwrap_or_else(|| "null".to_string());
+        let knots:Vec<String> = self.knots.iter().map(|k| format!("[{},{}]", k.x, k.y)).collect();
+        format!("{{\"motion\":{},\"knots\":[{}],\"tail_visits\":{}}}", motion, knots.join(","), self.tail_visits())
+    }
+}
+
+/// Run the motions on a rope, handing over the rope at the start and after every motion
+fn trace<F: FnMut(Option<&Motion>, &Rope)>(motions:&[Motion], length:usize, mut on_frame:F)
 {
-    let tail_count = 10; // part1 == 2
-    let mut knots = vec![Point::default(); tail_count];
-    let mut visited_by_tail:HashSet<Point> = HashSet::new();
-
-    let bf = BufReader::new(File::open(&file_path).expect(&file_path));
-    for line in bf.lines() {
-        let move_cmd = line.ok()?;
-        match move_cmd.split_once(" ") {
-            Some((d, c)) => {
-                match (Direction::from_str(d), u32::from_str_radix(c, 10)) {
-                    (Ok(d), Ok(c)) => {
-                        for _ in 0..c {
-                            let mut knots_iter = knots.iter_mut();
-                            let mut last_knot = knots_iter.next()?;
-                            // move the head first
-                            last_knot.move_position(1, d);
-                            // followers here
-                            while let Some(knot)  = knots_iter.next() {
-                                knot.follow(&last_knot);
-                                last_knot = knot;
-                            }
-                            visited_by_tail.insert(last_knot.clone());
-                        }
-                    },
-                    (_, _) => return None,
-                }
+    let mut rope = Rope::new(length, false);
+    on_frame(None, &rope);
+    for motion in motions {
+        rope.apply(motion);
+        on_frame(Some(motion), &rope);
+    }
+}
+
+/// The area every knot passes through over the whole run
+fn trace_view(motions:&[Motion], length:usize) -> BoundingBox
+{
+    let mut rope = Rope::new(length, false);
+    for motion in motions {
+        rope.apply(motion);
+    }
+    let mut view = BoundingBox::new(Point::default());
+    for bounds in &rope.bounds {
+        view.union(bounds);
+    }
+    view
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TraceMode {
+    /// every frame as text, headed by the motion like the puzzle examples
+    Text,
+    /// a JSON array of frames
+    Json,
+    /// one text frame at a time, waiting for enter in between
+    Step,
+}
+
+fn parse_motions<R: Read>(bf:BufReader<R>) -> Option<Vec<Motion>>
+{
+    bf.lines().map(|line| line.ok()?.parse::<Motion>().ok()).collect()
+}
+
+fn day9(file_path:&str, length:usize, track_all:bool) -> Option<Rope>
+{
+    let motions = parse_motions(BufReader::new(File::open(file_path).expect(file_path)))?;
+    let mut rope = Rope::new(length, track_all);
+    for motion in &motions {
+        rope.apply(motion);
+    }
+    Some(rope)
+}
+
+fn day9_trace(file_path:&str, length:usize, mode:TraceMode) -> Option<()>
+{
+    let motions = parse_motions(BufReader::new(File::open(file_path).expect(file_path)))?;
+    let view = trace_view(&motions, length);
+    let mut frames:Vec<String> = Vec::new();
+    let mut quit = false;
+    trace(&motions, length, |motion, rope| {
+        let title = motion.map(|m| m.to_string()).unwrap_or_else(|| "Initial State".to_string());
+        match mode {
+            TraceMode::Text => println!("== {} ==\n\n{}", title, rope.render(&view)),
+            TraceMode::Json => frames.push(rope.to_json(motion)),
+            TraceMode::Step if !quit => {
+                // clear the screen and home the cursor before each frame
+                print!("\x1b[2J\x1b[H== {} ==\n\n{}\n[enter] next, q quit: ", title, rope.render(&view));
+                let _ = std::io::stdout().flush();
+                let mut input = String::new();
+                quit = std::io::stdin().read_line(&mut input).map_or(true, |n| n == 0 || input.trim() == "q");
             },
-            _ => {return None;}
+            TraceMode::Step => {},
         }
+    });
+    if mode == TraceMode::Json {
+        println!("[{}]", frames.join(",\n "));
     }
-
-    Some(visited_by_tail.len())
+    Some(())
 }
 
 fn main()
 {
     let args:Vec<String> = env::args().collect();
-    if let None = args.get(1) {
-        println!("Usage: {} <path>", args[0]);
+    let usage = || {
+        println!("Usage: {} <path> [<rope length>] [--all | --trace text|json|step]", args[0]);
         std::process::exit(1);
+    };
+    if args.get(1).is_none() {
+        usage();
+    }
+    let track_all = args.iter().skip(2).any(|a| a == "--all");
+    let length = args.get(2).and_then(|l| l.parse::<usize>().ok()).filter(|&l| l > 0);
+    if let Some(pos) = args.iter().position(|a| a == "--trace") {
+        let mode = match args.get(pos + 1).map(|a| a.as_str()) {
+            Some("text") => TraceMode::Text,
+            Some("json") => TraceMode::Json,
+            Some("step") => TraceMode::Step,
+            _ => return usage(),
+        };
+        if day9_trace(&args[1], length.unwrap_or(10), mode).is_none() {
+            println!("invalid motion in {}", args[1]);
+            std::process::exit(1);
+        }
+        return;
+    }
+    let lengths = match length {
+        Some(length) => vec![length],
+        None => vec![2, 10], // part1 and part2
+    };
+    for length in lengths {
+        let rope = match day9(&args[1], length, track_all) {
+            Some(rope) => rope,
+            None => {
+                println!("invalid motion in {}", args[1]);
+                std::process::exit(1);
+            },
+        };
+        println!("rope of {} knots, tail visited {}", length, rope.tail_visits());
+        if track_all {
+            for (idx, bounds) in rope.bounds.iter().enumerate() {
+                println!("  knot {}: visited {}, bounds {}..{}", idx, rope.visits(idx).unwrap_or(0), bounds.min, bounds.max);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_motions, trace, trace_view, BoundingBox, Direction, Motion, Point, Rope};
+    use std::io::BufReader;
+
+    static DATA:&str = "R 4\nU 4\nL 3\nD 1\nR 4\nD 1\nL 5\nR 2\n";
+    static LARGER:&str = "R 5\nU 8\nL 8\nD 3\nR 17\nD 10\nL 25\nU 20\n";
+
+    fn simulate(data:&str, length:usize, track_all:bool) -> Rope {
+        let mut rope = Rope::new(length, track_all);
+        for motion in parse_motions(BufReader::new(data.as_bytes())).unwrap() {
+            rope.apply(&motion);
+        }
+        rope
+    }
+
+    #[test]
+    fn test_follow() {
+        // every offset a leader can end up at after one step away from a touching follower
+        for dx in -2..=2 {
+            for dy in -2..=2 {
+                let head = Point::new(dx, dy);
+                let mut tail = Point::default();
+                tail.follow(&head);
+                if dx.abs() <= 1 && dy.abs() <= 1 {
+                    assert_eq!(tail, Point::default(), "touching at ({},{}) should not move", dx, dy);
+                    continue;
+                }
+                // one step at most on each axis, towards the head, ending up touching
+                assert_eq!(tail, Point::new(dx.signum(), dy.signum()), "offset ({},{})", dx, dy);
+                assert!(tail.distance(&head) == 1, "offset ({},{})", dx, dy);
+                // the same from anywhere on the grid
+                let mut tail = Point::new(-7, 11);
+                tail.follow(&Point::new(-7 + dx, 11 + dy));
+                assert_eq!(tail, Point::new(-7 + dx.signum(), 11 + dy.signum()));
+            }
+        }
+        // and nothing further away than that needs more than one step per axis
+        for dx in -5..=5 {
+            for dy in -5..=5 {
+                let mut tail = Point::default();
+                tail.follow(&Point::new(dx, dy));
+                assert!(tail.x.abs() <= 1 && tail.y.abs() <= 1);
+            }
+        }
+
+        assert_eq!(Point::new(1, 1).distance(&Point::new(0, 0)), 1);
+        assert_eq!(Point::new(2, -1).distance(&Point::new(0, 0)), 2);
+        let mut tail = Point::new(1, 1);
+        tail.follow(&Point::new(2, 3));
+        assert_eq!(tail, Point::new(2, 2));
+        tail.follow(&Point::new(4, 2));
+        assert_eq!(tail, Point::new(3, 2));
+    }
+
+    #[test]
+    fn test_rope() {
+        assert_eq!("R 4".parse::<Motion>().ok(), Some(Motion{direction: Direction::Right, count: 4}));
+        assert!("X 4".parse::<Motion>().is_err());
+        assert!("R four".parse::<Motion>().is_err());
+        assert!(parse_motions(BufReader::new("R 1\nbad\n".as_bytes())).is_none());
+
+        assert_eq!(simulate(DATA, 2, false).tail_visits(), 13);
+        assert_eq!(simulate(DATA, 10, false).tail_visits(), 1);
+        assert_eq!(simulate(LARGER, 10, false).tail_visits(), 36);
+
+        let rope = simulate(DATA, 10, true);
+        assert_eq!(rope.knots[0], Point::new(2, 2));
+        assert_eq!(rope.tail(), Point::default());
+        assert_eq!(rope.bounds[0], BoundingBox{min: Point::new(0, 0), max: Point::new(5, 4)});
+        assert_eq!(rope.bounds[9], BoundingBox::new(Point::default()));
+        assert_eq!(rope.visits(1), Some(simulate(DATA, 2, false).tail_visits()));
+        assert_eq!(rope.visits(9), Some(1));
+        assert_eq!(rope.visits(10), None);
+        // each knot visits no more places than the one in front of it
+        for idx in 1..10 {
+            assert!(rope.visits(idx) <= rope.visits(idx - 1));
+        }
+
+        let rope = simulate(LARGER, 10, false);
+        assert_eq!(rope.visits(9), Some(36));
+        assert_eq!(rope.visits(0), None);
+        assert_eq!(Rope::new(1, false).tail_visits(), 1);
+        assert_eq!(simulate(DATA, 1, false).tail_visits(), simulate(DATA, 10, true).visits(0).unwrap());
+    }
+
+    #[test]
+    fn test_render() {
+        let motions = parse_motions(BufReader::new(DATA.as_bytes())).unwrap();
+        assert_eq!(motions.iter().map(|m| m.to_string()).collect::<Vec<String>>().join("\n") + "\n", DATA);
+
+        let view = BoundingBox{min: Point::new(0, 0), max: Point::new(5, 4)};
+        assert_eq!(trace_view(&motions, 10), view);
+        let mut frames = Vec::new();
+        trace(&motions, 10, |motion, rope| frames.push((motion.copied(), rope.render(&view), rope.to_json(motion))));
+        assert_eq!(frames.len(), motions.len() + 1);
+        assert_eq!(frames[0].1, "......\n......\n......\n......\nH.....\n");
+        assert_eq!(frames[0].2, r#"{"motion":null,"knots":[[0,0],[0,0],[0,0],[0,0],[0,0],[0,0],[0,0],[0,0],[0,0],[0,0]],"tail_visits":1}"#);
+        // == R 4 == and == U 4 == from the puzzle
+        assert_eq!(frames[1].1, "......\n......\n......\n......\n4321H.\n");
+        assert_eq!(frames[2].1, "....H.\n....1.\n..432.\n.5....\n6.....\n");
+        assert_eq!(frames[2].0, Some(Motion{direction: Direction::Up, count: 4}));
+        assert!(frames[2].2.starts_with(r#"{"motion":"U 4","knots":[[4,4],[4,3],[4,2],[3,2],[2,2],[1,1],[0,0],"#));
+
+        // a two knot rope draws T and the trail
+        let mut last = String::new();
+        trace(&motions, 2, |_, rope| last = rope.render(&view));
+        assert_eq!(last, concat!(
+            "..##..\n",
+            "...##.\n",
+            ".TH##.\n",
+            "....#.\n",
+            "s###..\n",
+        ));
     }
-    dbg!(day7(&args[1]));
 }